@@ -0,0 +1,86 @@
+use crate::ratings::Title;
+use std::{fmt, str::FromStr};
+
+/// Role given from the title or flags of a player's account rather than from their ratings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountRole {
+    Title(Title),
+    Patron,
+    Verified,
+}
+
+impl AccountRole {
+    pub fn get_name(&self) -> String {
+        match self {
+            AccountRole::Title(title) => title.to_string(),
+            AccountRole::Patron => "lichess patron".to_string(),
+            AccountRole::Verified => "lichess verified".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for AccountRole {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        trace!("AccountRole::fmt() called");
+        write!(f, "AccountRole<{:?}>", self)
+    }
+}
+
+impl FromStr for AccountRole {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            // Plain "Patron" and "Verified" roles are too common on Discord to be claimed
+            "lichess patron" => Ok(AccountRole::Patron),
+            "lichess verified" => Ok(AccountRole::Verified),
+            // Titles must be written in capitals, so a role named "gm" or "im" isn't picked up
+            _ if s == s.to_uppercase() => Ok(AccountRole::Title(s.parse()?)),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_correctly_detects_titles() {
+        assert_eq!(
+            "GM".parse::<AccountRole>().unwrap(),
+            AccountRole::Title(Title::GM)
+        );
+        assert_eq!(
+            "WFM".parse::<AccountRole>().unwrap(),
+            AccountRole::Title(Title::WFM)
+        );
+        assert!("gm".parse::<AccountRole>().is_err());
+        assert!("BOT".parse::<AccountRole>().is_err());
+    }
+
+    #[test]
+    fn parse_correctly_detects_flags() {
+        assert_eq!(
+            "Lichess Patron".parse::<AccountRole>().unwrap(),
+            AccountRole::Patron
+        );
+        assert_eq!(
+            "lichess verified".parse::<AccountRole>().unwrap(),
+            AccountRole::Verified
+        );
+        assert!("Patron".parse::<AccountRole>().is_err());
+        assert!("Verified".parse::<AccountRole>().is_err());
+    }
+
+    #[test]
+    fn get_name_can_be_parsed_back() {
+        for role in [
+            AccountRole::Title(Title::LM),
+            AccountRole::Patron,
+            AccountRole::Verified,
+        ] {
+            assert_eq!(role.get_name().parse::<AccountRole>().unwrap(), role);
+        }
+    }
+}
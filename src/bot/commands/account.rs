@@ -1,7 +1,15 @@
-use super::{rating_update::update_ratings, Response, Result};
+use super::{
+    rating_update::{update_rating_roles, update_ratings},
+    Response, Result,
+};
 use crate::{
-    bot::run::{PoolContainer, RoleManagerContainer},
+    bot::{
+        role_manager::RoleManager,
+        run::{ChesscomClientContainer, PoolContainer, RoleManagerContainer},
+    },
+    chesscom,
     models::{Challenge, User},
+    ratings::Provider,
 };
 use serenity::{
     framework::standard::{macros::command, CommandResult},
@@ -9,12 +17,43 @@ use serenity::{
     prelude::*,
 };
 
-pub async fn unlink(ctx: &Context, guild_id: u64, discord_id: u64) -> Result<Response> {
+// Audit log reason for the roles removed from members deleting their information
+const UNLINK_REASON: &str = "Liro: member unlinked their accounts";
+
+async fn remove_rating_roles(
+    ctx: &Context,
+    rm: &RoleManager,
+    guild_id: u64,
+    discord_id: u64,
+) -> Result<()> {
+    trace!("remove_rating_roles() called");
+    let role_ids = rm.filter_manageable(guild_id, rm.other_rating_range_roles(guild_id, []));
+
+    update_rating_roles(
+        &ctx.http,
+        guild_id,
+        discord_id,
+        vec![],
+        role_ids,
+        UNLINK_REASON,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Unlinks the `account` of the user, or deletes all their information if no account is given
+pub async fn unlink(
+    ctx: &Context,
+    guild_id: u64,
+    discord_id: u64,
+    account: Option<&str>,
+) -> Result<Response> {
     trace!("unlink() called");
 
     info!(
-        "Deleting data for discord_id={} in guild_id={}",
-        discord_id, guild_id,
+        "Unlinking {:?} for discord_id={} in guild_id={}",
+        account, discord_id, guild_id,
     );
 
     let pool;
@@ -25,44 +64,43 @@ pub async fn unlink(ctx: &Context, guild_id: u64, discord_id: u64) -> Result<Res
         rm = data.get::<RoleManagerContainer>().unwrap().clone();
     }
 
-    let response = match User::find(&pool, guild_id, discord_id).await {
-        Ok(Some(mut user)) => {
-            let member = ctx
-                .http
-                .get_member(guild_id, discord_id)
-                .await
-                .map_err(|e| {
-                    error!(
-                        "Could not retrieve user information for discord_id={} in guild_id={}: {}",
-                        discord_id, guild_id, e
-                    );
-                    e
-                })?;
-            let role_ids = rm.other_rating_range_roles(guild_id, &[]);
-
-            for role_id in role_ids {
-                if member.roles.contains(&RoleId(role_id)) {
-                    ctx.http
-                        .remove_member_role(guild_id, discord_id, role_id)
-                        .await
-                        .map_err(|e| {
-                            error!(
-                                "Could not remove role_id={} from discord_id={}: {}",
-                                role_id, discord_id, e
-                            );
-                            e
-                        })?;
-                }
+    let response = match (User::find(&pool, guild_id, discord_id).await, account) {
+        (Ok(Some(mut user)), Some(name)) => {
+            // Unlinking the last account deletes the user, so their roles go first
+            let last = matches!(user.get_accounts(), [account] if account.is_named(name));
+            if last {
+                remove_rating_roles(ctx, &rm, guild_id, discord_id).await?;
             }
 
+            match user.unlink(&pool, name).await? {
+                Some(account) if last => Response::PrivateSentence(format!(
+                    "{} was your last linked account, so your information was deleted. \
+                    Toodles! :wave:",
+                    account
+                )),
+                Some(account) => Response::PrivateSentence(format!(
+                    "{} is not linked anymore. The roles it earned you stay until you update \
+                    your roles with `/rating`.",
+                    account
+                )),
+                None => Response::PrivateSentence(format!(
+                    "You didn't link any account called {}. You can write `lichess:<username>` \
+                    or `chesscom:<username>` if you linked accounts with the same name on both \
+                    sites.",
+                    name
+                )),
+            }
+        }
+        (Ok(Some(mut user)), None) => {
+            remove_rating_roles(ctx, &rm, guild_id, discord_id).await?;
             user.delete(&pool).await?;
 
             Response::PrivateSentence("User information deleted. Toodles! :wave:".to_string())
         }
-        Ok(None) => {
+        (Ok(None), _) => {
             Response::PrivateSentence("I don't see any data to delete:question:".to_string())
         }
-        Err(why) => {
+        (Err(why), _) => {
             error!("Unable to query database: {}", why);
             Response::Sentence("Internal bot error. @teotwaki, I'm scared.".to_string())
         }
@@ -71,13 +109,54 @@ pub async fn unlink(ctx: &Context, guild_id: u64, discord_id: u64) -> Result<Res
     Ok(response)
 }
 
+/// Makes `account` the primary account of the user
+pub async fn primary(
+    ctx: &Context,
+    guild_id: u64,
+    discord_id: u64,
+    account: &str,
+) -> Result<Response> {
+    trace!("primary() called");
+    let pool;
+    {
+        let data = ctx.data.read().await;
+        pool = data.get::<PoolContainer>().unwrap().clone();
+    }
+
+    let mut user = match User::find(&pool, guild_id, discord_id).await? {
+        Some(user) => user,
+        None => {
+            return Ok(Response::PrivateSentence(
+                "You didn't link any account yet. Please use `/link` first.".to_string(),
+            ))
+        }
+    };
+
+    let response = match user.set_primary(&pool, account).await? {
+        Some(account) => Response::PrivateSentence(format!(
+            "{} is now your primary account. Use `/rating` to update your roles.",
+            account
+        )),
+        None => {
+            let accounts: Vec<String> = user.get_accounts().iter().map(|a| a.to_string()).collect();
+            Response::PrivateSentence(format!(
+                "You didn't link any account called {}. Your linked accounts are: {}.",
+                account,
+                accounts.join(", ")
+            ))
+        }
+    };
+
+    Ok(response)
+}
+
 #[command]
 async fn gdpr(ctx: &Context, msg: &Message) -> CommandResult {
     trace!("gdpr() called");
     let guild_id = *msg.guild_id.unwrap().as_u64();
     let discord_id = *msg.author.id.as_u64();
 
-    match unlink(ctx, guild_id, discord_id).await? {
+    match unlink(ctx, guild_id, discord_id, None).await? {
         Response::PrivateSentence(s) | Response::Sentence(s) => {
             msg.channel_id.send_message(&ctx, |m| m.content(s)).await?;
         }
@@ -87,7 +166,12 @@ async fn gdpr(ctx: &Context, msg: &Message) -> CommandResult {
     Ok(())
 }
 
-pub async fn link(ctx: &Context, guild_id: u64, discord_id: u64) -> Result<Response> {
+pub async fn link(
+    ctx: &Context,
+    guild_id: u64,
+    discord_id: u64,
+    chesscom_username: Option<&str>,
+) -> Result<Response> {
     trace!("link() called");
     info!(
         "Handling link command for discord_id={} in guild_id={}",
@@ -98,6 +182,22 @@ pub async fn link(ctx: &Context, guild_id: u64, discord_id: u64) -> Result<Respo
         let data = ctx.data.read().await;
         pool = data.get::<PoolContainer>().unwrap().clone();
     }
+
+    if let Some(username) = chesscom_username {
+        let challenge = Challenge::new_chesscom(&pool, guild_id, discord_id, username).await?;
+
+        let whisper = format!(
+            "To prove that you own the chess.com account {}, please add the code `{}` to the \
+            location field of your [chess.com profile](https://www.chess.com/settings), then use \
+            `/verify`. The code is valid for 24 hours, and you can remove it from your profile \
+            once your account is linked.",
+            username,
+            challenge.nonce()
+        );
+
+        return Ok(Response::PrivateSentence(whisper));
+    }
+
     let challenge = Challenge::new(&pool, guild_id, discord_id).await?;
 
     let whisper = format!(
@@ -108,13 +208,85 @@ pub async fn link(ctx: &Context, guild_id: u64, discord_id: u64) -> Result<Respo
     Ok(Response::PrivateSentence(whisper))
 }
 
+pub async fn verify(ctx: &Context, guild_id: u64, discord_id: u64) -> Result<Response> {
+    trace!("verify() called");
+    info!(
+        "Handling verify command for discord_id={} in guild_id={}",
+        discord_id, guild_id,
+    );
+    let pool;
+    let chesscom;
+    {
+        let data = ctx.data.read().await;
+        pool = data.get::<PoolContainer>().unwrap().clone();
+        chesscom = data.get::<ChesscomClientContainer>().unwrap().clone();
+    }
+
+    let challenge = match Challenge::find_chesscom(&pool, guild_id, discord_id).await? {
+        Some(challenge) => challenge,
+        None => {
+            return Ok(Response::PrivateSentence(
+                "There is no chess.com account waiting to be verified. Please use \
+                `/link chesscom:<username>` first."
+                    .to_string(),
+            ))
+        }
+    };
+
+    // The challenge is only ever found if it carries a chess.com username
+    let username = challenge.chesscom_username().unwrap_or_default();
+
+    let chesscom_user = match chesscom.fetch_user(username).await {
+        Ok(chesscom_user) => chesscom_user,
+        Err(chesscom::Error::NotFound) => {
+            return Ok(Response::PrivateSentence(format!(
+                "I couldn't find the chess.com account {}. Please check the spelling and use \
+                `/link chesscom:<username>` again.",
+                username
+            )))
+        }
+        Err(why) => return Err(why.into()),
+    };
+
+    if !chesscom_user.profile_contains(challenge.nonce()) {
+        return Ok(Response::PrivateSentence(format!(
+            "I couldn't find the code `{}` in the location field of the chess.com profile of {}. \
+            Please note that chess.com can take a few minutes to show profile changes.",
+            challenge.nonce(),
+            chesscom_user.get_username()
+        )));
+    }
+
+    let username = chesscom_user.get_username();
+
+    if let Some(user) =
+        User::find_by_username(&pool, guild_id, Provider::Chesscom, username).await?
+    {
+        if user.discord_id() != discord_id {
+            return Ok(Response::PrivateSentence(
+                "The account you are trying to link is already in use on this Discord server"
+                    .to_string(),
+            ));
+        }
+    }
+
+    User::link(&pool, guild_id, discord_id, Provider::Chesscom, username).await?;
+    challenge.delete(&pool).await?;
+
+    Ok(Response::PrivateSentence(format!(
+        "Your chess.com account {} is now linked :tada: You can now remove the code from your \
+        profile and use `/rating` to get your roles.",
+        username
+    )))
+}
+
 #[command]
 async fn account(ctx: &Context, msg: &Message) -> CommandResult {
     trace!("account() called");
     let guild_id = *msg.guild_id.unwrap().as_u64();
     let discord_id = *msg.author.id.as_u64();
 
-    let response = link(ctx, guild_id, discord_id).await?;
+    let response = link(ctx, guild_id, discord_id, None).await?;
 
     if let Response::PrivateSentence(whisper) = response {
         let message = match msg.author.dm(&ctx, |m| m.content(whisper)).await {
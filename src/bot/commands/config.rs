@@ -0,0 +1,386 @@
+use super::{
+    get_bool_option, get_channel_option, get_integer_option, get_role_option, get_string_option,
+    roles::reload_role, Response, Result,
+};
+use crate::{
+    bot::run::{PoolContainer, RoleManagerContainer},
+    lichess::Format,
+    models::{Guild, RoleSource},
+};
+use serenity::{
+    model::interactions::application_command::ApplicationCommandInteractionDataOption, prelude::*,
+};
+
+async fn formats(ctx: &Context, guild_id: u64, formats: Option<&str>) -> Result<Response> {
+    trace!("formats() called");
+    let pool;
+    {
+        let data = ctx.data.read().await;
+        pool = data.get::<PoolContainer>().unwrap().clone();
+    }
+
+    let mut guild = Guild::find_or_create(&pool, guild_id, "").await?;
+
+    let formats = match formats {
+        Some(formats) => formats,
+        None => {
+            let keys: Vec<&str> = guild.get_formats().iter().map(|f| f.key()).collect();
+            return Ok(Response::PrivateSentence(format!(
+                "The following formats are shown by `/rating`: {}",
+                keys.join(", ")
+            )));
+        }
+    };
+
+    let mut parsed = vec![];
+    for name in formats
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+    {
+        match name.to_lowercase().parse::<Format>() {
+            Ok(format) if !parsed.contains(&format) => parsed.push(format),
+            Ok(_) => {}
+            Err(_) => {
+                return Ok(Response::PrivateSentence(format!(
+                    "I don't know the format `{}`. Please use a comma separated list of formats, \
+                    such as `blitz, rapid, chess960`.",
+                    name
+                )))
+            }
+        }
+    }
+
+    if parsed.is_empty() {
+        return Ok(Response::PrivateSentence(
+            "Please give me at least one format to show.".to_string(),
+        ));
+    }
+
+    info!("Setting formats {:?} for guild_id={}", parsed, guild_id);
+    guild.set_formats(&pool, parsed).await?;
+
+    Ok(Response::PrivateSentence(
+        "The formats shown by `/rating` have been updated.".to_string(),
+    ))
+}
+
+async fn eligibility(
+    ctx: &Context,
+    guild_id: u64,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Result<Response> {
+    trace!("eligibility() called");
+    let pool;
+    {
+        let data = ctx.data.read().await;
+        pool = data.get::<PoolContainer>().unwrap().clone();
+    }
+
+    let mut guild = Guild::find_or_create(&pool, guild_id, "").await?;
+
+    if options.is_empty() {
+        return Ok(Response::PrivateSentence(format!(
+            "Current eligibility rules: {}.",
+            guild.get_policy()
+        )));
+    }
+
+    let mut policy = guild.get_policy().clone();
+
+    if let Some(allow_provisional) = get_bool_option(options, "provisional") {
+        policy.set_allow_provisional(allow_provisional);
+    }
+
+    if let Some(min_games) = get_integer_option(options, "min_games") {
+        let format = match get_string_option(options, "format")
+            .map(|f| f.to_lowercase().parse::<Format>())
+        {
+            Some(Ok(format)) => format,
+            _ => {
+                return Ok(Response::PrivateSentence(
+                    "Please tell me which format the minimum number of games applies to, e.g. \
+                    `format:blitz`."
+                        .to_string(),
+                ))
+            }
+        };
+
+        policy.set_min_games(format, min_games.clamp(0, u32::MAX as i64) as u32);
+    }
+
+    if let Some(max_rd) = get_integer_option(options, "max_rd") {
+        if max_rd > 0 {
+            policy.set_max_rd(max_rd.min(i16::MAX as i64) as i16);
+        } else {
+            policy.set_max_rd(None);
+        }
+    }
+
+    info!("Setting policy {:?} for guild_id={}", policy, guild_id);
+    guild.set_policy(&pool, policy).await?;
+
+    Ok(Response::PrivateSentence(format!(
+        "Eligibility rules updated: {}.",
+        guild.get_policy()
+    )))
+}
+
+/// Accepts either a lichess team ID or the URL of the team page
+fn parse_team_id(team: &str) -> String {
+    team.trim()
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+async fn team(
+    ctx: &Context,
+    guild_id: u64,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Result<Response> {
+    trace!("team() called");
+    let pool;
+    let mut rm;
+    {
+        let data = ctx.data.read().await;
+        pool = data.get::<PoolContainer>().unwrap().clone();
+        rm = data.get::<RoleManagerContainer>().unwrap().clone();
+    }
+
+    let mut guild = Guild::find_or_create(&pool, guild_id, "").await?;
+
+    let team_id = match get_string_option(options, "team").map(parse_team_id) {
+        Some(team_id) if !team_id.is_empty() => team_id,
+        _ => {
+            if guild.get_team_roles().is_empty() {
+                return Ok(Response::PrivateSentence(
+                    "No lichess team is mapped to a role.".to_string(),
+                ));
+            }
+
+            let mappings: Vec<String> = guild
+                .get_team_roles()
+                .iter()
+                .map(|(team_id, role_id)| format!("`{}` → <@&{}>", team_id, role_id))
+                .collect();
+            return Ok(Response::PrivateSentence(format!(
+                "Lichess teams mapped to roles: {}",
+                mappings.join(", ")
+            )));
+        }
+    };
+
+    let role_id = get_role_option(options, "role");
+    info!(
+        "Mapping lichess team {} to role_id={:?} in guild_id={}",
+        team_id, role_id, guild_id
+    );
+
+    if let Some(previous) = guild.set_team_role(&pool, &team_id, role_id).await? {
+        reload_role(ctx, &mut rm, &guild, previous).await?;
+    }
+
+    match role_id {
+        Some(role_id) => {
+            reload_role(ctx, &mut rm, &guild, role_id).await?;
+            Ok(Response::PrivateSentence(format!(
+                "Members of the lichess team `{}` will be given <@&{}> when they update their \
+                ratings.",
+                team_id, role_id
+            )))
+        }
+        None => Ok(Response::PrivateSentence(format!(
+            "The lichess team `{}` isn't mapped to a role anymore.",
+            team_id
+        ))),
+    }
+}
+
+async fn accounts(ctx: &Context, guild_id: u64, source: Option<&str>) -> Result<Response> {
+    trace!("accounts() called");
+    let pool;
+    {
+        let data = ctx.data.read().await;
+        pool = data.get::<PoolContainer>().unwrap().clone();
+    }
+
+    let mut guild = Guild::find_or_create(&pool, guild_id, "").await?;
+
+    let source = match source.map(|s| s.parse::<RoleSource>()) {
+        Some(Ok(source)) => source,
+        Some(Err(_)) | None => {
+            return Ok(Response::PrivateSentence(format!(
+                "Roles of members with several accounts are based on their {}.",
+                guild.get_role_source()
+            )))
+        }
+    };
+
+    info!("Setting role source {:?} for guild_id={}", source, guild_id);
+    guild.set_role_source(&pool, source).await?;
+
+    Ok(Response::PrivateSentence(format!(
+        "Roles of members with several accounts are now based on their {}.",
+        source
+    )))
+}
+
+async fn demotion(
+    ctx: &Context,
+    guild_id: u64,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Result<Response> {
+    trace!("demotion() called");
+    let pool;
+    {
+        let data = ctx.data.read().await;
+        pool = data.get::<PoolContainer>().unwrap().clone();
+    }
+
+    let mut guild = Guild::find_or_create(&pool, guild_id, "").await?;
+
+    if options.is_empty() {
+        return Ok(Response::PrivateSentence(format!(
+            "Currently, {}.",
+            guild.get_hysteresis()
+        )));
+    }
+
+    let mut hysteresis = guild.get_hysteresis();
+
+    if let Some(grace) = get_integer_option(options, "grace") {
+        hysteresis.grace = grace.clamp(0, i16::MAX as i64) as i16;
+    }
+
+    if let Some(hours) = get_integer_option(options, "hours") {
+        hysteresis.delay_hours = hours.clamp(0, u32::MAX as i64) as u32;
+    }
+
+    info!(
+        "Setting hysteresis {:?} for guild_id={}",
+        hysteresis, guild_id
+    );
+    guild.set_hysteresis(&pool, hysteresis).await?;
+
+    Ok(Response::PrivateSentence(format!(
+        "From now on, {}.",
+        hysteresis
+    )))
+}
+
+async fn flagged(
+    ctx: &Context,
+    guild_id: u64,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Result<Response> {
+    trace!("flagged() called");
+    let pool;
+    {
+        let data = ctx.data.read().await;
+        pool = data.get::<PoolContainer>().unwrap().clone();
+    }
+
+    let mut guild = Guild::find_or_create(&pool, guild_id, "").await?;
+
+    if options.is_empty() {
+        return Ok(Response::PrivateSentence(format!(
+            "Currently, {}.",
+            guild.get_flagged_policy()
+        )));
+    }
+
+    let mut policy = guild.get_flagged_policy();
+
+    if let Some(strip_roles) = get_bool_option(options, "strip") {
+        policy.strip_roles = strip_roles;
+    }
+
+    if let Some(notify) = get_bool_option(options, "notify") {
+        policy.notify = notify;
+    }
+
+    info!(
+        "Setting flagged account policy {:?} for guild_id={}",
+        policy, guild_id
+    );
+    guild.set_flagged_policy(&pool, policy).await?;
+
+    Ok(Response::PrivateSentence(format!(
+        "From now on, {}.",
+        policy
+    )))
+}
+
+async fn alerts(ctx: &Context, guild_id: u64, channel_id: Option<u64>) -> Result<Response> {
+    trace!("alerts() called");
+    let pool;
+    {
+        let data = ctx.data.read().await;
+        pool = data.get::<PoolContainer>().unwrap().clone();
+    }
+
+    let mut guild = Guild::find_or_create(&pool, guild_id, "").await?;
+
+    let channel_id = match channel_id {
+        Some(channel_id) => channel_id,
+        None => {
+            return Ok(Response::PrivateSentence(match guild.get_alert_channel() {
+                Some(channel_id) => {
+                    format!("Warnings for the admins are posted in <#{}>.", channel_id)
+                }
+                None => "Warnings for the admins are posted in the system channel of the server."
+                    .to_string(),
+            }))
+        }
+    };
+
+    info!(
+        "Setting alert channel_id={} for guild_id={}",
+        channel_id, guild_id
+    );
+    guild.set_alert_channel(&pool, channel_id).await?;
+
+    Ok(Response::PrivateSentence(format!(
+        "Warnings for the admins will now be posted in <#{}>.",
+        channel_id
+    )))
+}
+
+/// Handles the `/config` admin command and its subcommands
+pub async fn config(
+    ctx: &Context,
+    guild_id: u64,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Result<Response> {
+    trace!("config() called");
+    let subcommand = match options.first() {
+        Some(subcommand) => subcommand,
+        None => {
+            return Ok(Response::PrivateSentence(
+                "Please pick a setting to change.".to_string(),
+            ))
+        }
+    };
+
+    match subcommand.name.as_str() {
+        "formats" => {
+            let value = get_string_option(&subcommand.options, "formats");
+            formats(ctx, guild_id, value).await
+        }
+        "eligibility" => eligibility(ctx, guild_id, &subcommand.options).await,
+        "team" => team(ctx, guild_id, &subcommand.options).await,
+        "accounts" => {
+            let value = get_string_option(&subcommand.options, "roles");
+            accounts(ctx, guild_id, value).await
+        }
+        "demotion" => demotion(ctx, guild_id, &subcommand.options).await,
+        "flagged" => flagged(ctx, guild_id, &subcommand.options).await,
+        "alerts" => {
+            let channel_id = get_channel_option(&subcommand.options, "channel");
+            alerts(ctx, guild_id, channel_id).await
+        }
+        _ => unreachable!(),
+    }
+}
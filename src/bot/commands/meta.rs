@@ -10,11 +10,13 @@ async fn help(ctx: &Context, msg: &Message) -> CommandResult {
     let message = "Hi, I'm liro!\n\
                    I help automate role assignments based on your rating. To get started, please \
                    link your lichess account to your Discord user by saying `ohnomy account` (or \
-                   `/link`)\n\
+                   `/link`). chess.com accounts can be linked with `/link chesscom:<username>` \
+                   followed by `/verify`. You can link several accounts, and pick the one your \
+                   roles are based on with `/primary`\n\
                    After that, you can ask me to retrieve your ratings and update your Discord \
                    roles by saying `ohnomy rating` (or `/rating`)\n\
                    If you want me to forget everything I know about you, just say `ohnomy gdpr` \
-                   (or `/unlink`)";
+                   (or `/unlink`). `/unlink account:<username>` only unlinks that account";
     msg.channel_id.say(&ctx.http, message).await?;
 
     Ok(())
@@ -1,9 +1,19 @@
 pub mod account;
+pub mod config;
 pub mod meta;
 pub mod rating_update;
+pub mod roles;
 
-use crate::models;
-use serenity::{builder::CreateEmbed, prelude::SerenityError};
+use crate::{chesscom, db::Pool, lichess, models, ratings};
+use serenity::{
+    builder::CreateEmbed,
+    http::Http,
+    model::{
+        guild::Member, id::ChannelId,
+        interactions::application_command::ApplicationCommandInteractionDataOption,
+    },
+    prelude::SerenityError,
+};
 use thiserror::Error;
 
 pub enum Response {
@@ -19,6 +29,196 @@ pub enum Error {
     Model(#[from] models::Error),
     #[error("Discord error: {0}")]
     Discord(#[from] SerenityError),
+    #[error("chess.com error: {0}")]
+    Chesscom(#[from] chesscom::Error),
+}
+
+impl Error {
+    /// Lichess error behind the failure, if lichess caused it
+    fn lichess_error(&self) -> Option<&lichess::Error> {
+        match self {
+            Error::Model(models::Error::Lichess(e))
+            | Error::Model(models::Error::Rating(ratings::Error::Lichess(e))) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Chess.com error behind the failure, if chess.com caused it
+    fn chesscom_error(&self) -> Option<&chesscom::Error> {
+        match self {
+            Error::Chesscom(e)
+            | Error::Model(models::Error::Rating(ratings::Error::Chesscom(e))) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Seconds to wait before lichess or chess.com accept requests again, if either rate limited
+    /// the bot
+    pub fn retry_after(&self) -> Option<u64> {
+        match (self.lichess_error(), self.chesscom_error()) {
+            (Some(lichess::Error::RateLimited(seconds)), _)
+            | (_, Some(chesscom::Error::RateLimited(seconds))) => Some(*seconds),
+            _ => None,
+        }
+    }
+
+    /// Explains the failure to members when lichess or chess.com caused it, rather than the bot
+    pub fn describe(&self) -> Option<String> {
+        self.lichess_error()
+            .map(describe_lichess)
+            .or_else(|| self.chesscom_error().map(describe_chesscom))
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+/// Explains a lichess failure to members
+fn describe_lichess(e: &lichess::Error) -> String {
+    match e {
+        lichess::Error::NotFound => "Lichess couldn't find one of your accounts, it may have \
+            been closed. You can remove it with `/unlink`."
+            .to_string(),
+        lichess::Error::Unauthorized => {
+            "Lichess refused my credentials. Please let the people running Liro know.".to_string()
+        }
+        lichess::Error::RateLimited(seconds) => format!(
+            "Lichess is asking me to slow down. Please try again in {} seconds.",
+            seconds
+        ),
+        lichess::Error::ServerError(_) => {
+            "Lichess seems to be having trouble right now. Please try again later.".to_string()
+        }
+        lichess::Error::Decode(_) => {
+            "Lichess sent me an answer I don't understand. Please try again later.".to_string()
+        }
+        lichess::Error::Network(_) => {
+            "I couldn't reach lichess. Please try again later.".to_string()
+        }
+    }
+}
+
+/// Explains a chess.com failure to members
+fn describe_chesscom(e: &chesscom::Error) -> String {
+    match e {
+        chesscom::Error::NotFound => "Chess.com couldn't find one of your accounts, it may have \
+            been renamed or closed. You can remove it with `/unlink`."
+            .to_string(),
+        chesscom::Error::RateLimited(seconds) => format!(
+            "Chess.com is asking me to slow down. Please try again in {} seconds.",
+            seconds
+        ),
+        chesscom::Error::ServerError(_) => {
+            "Chess.com seems to be having trouble right now. Please try again later.".to_string()
+        }
+        chesscom::Error::Network(_) => {
+            "I couldn't reach chess.com. Please try again later.".to_string()
+        }
+    }
+}
+
+/// Looks up the value of a string option passed to an application command
+pub fn get_string_option<'a>(
+    options: &'a [ApplicationCommandInteractionDataOption],
+    name: &str,
+) -> Option<&'a str> {
+    options
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_str())
+}
+
+/// Looks up the value of a boolean option passed to an application command
+pub fn get_bool_option(
+    options: &[ApplicationCommandInteractionDataOption],
+    name: &str,
+) -> Option<bool> {
+    options
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_bool())
+}
+
+/// Looks up the value of an integer option passed to an application command
+pub fn get_integer_option(
+    options: &[ApplicationCommandInteractionDataOption],
+    name: &str,
+) -> Option<i64> {
+    options
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_i64())
+}
+
+/// Looks up the ID of the role passed as an option to an application command
+pub fn get_role_option(
+    options: &[ApplicationCommandInteractionDataOption],
+    name: &str,
+) -> Option<u64> {
+    get_string_option(options, name).and_then(|v| v.parse().ok())
+}
+
+/// Looks up the ID of the user passed as an option to an application command
+pub fn get_user_option(
+    options: &[ApplicationCommandInteractionDataOption],
+    name: &str,
+) -> Option<u64> {
+    get_string_option(options, name).and_then(|v| v.parse().ok())
+}
+
+/// Looks up the ID of the channel passed as an option to an application command
+pub fn get_channel_option(
+    options: &[ApplicationCommandInteractionDataOption],
+    name: &str,
+) -> Option<u64> {
+    get_string_option(options, name).and_then(|v| v.parse().ok())
+}
+
+// Discord refuses messages longer than 2000 characters
+pub(super) const MAX_MESSAGE_LENGTH: usize = 2000;
+
+/// Posts `message` for the admins of `guild_id`
+///
+/// The message goes to the channel set with `/config alerts`, or to the system channel of the
+/// guild if there is none. It is only logged if neither exists.
+pub async fn notify_admins(http: &Http, pool: &Pool, guild_id: u64, message: &str) -> Result<()> {
+    trace!("notify_admins() called");
+    let guild = models::Guild::find_or_create(pool, guild_id, "").await?;
+    let channel_id = match guild.get_alert_channel() {
+        Some(channel_id) => Some(ChannelId(channel_id)),
+        None => http.get_guild(guild_id).await?.system_channel_id,
+    };
+
+    let channel_id = match channel_id {
+        Some(channel_id) => channel_id,
+        None => {
+            warn!(
+                "No channel to notify the admins of guild_id={}: {}",
+                guild_id, message
+            );
+            return Ok(());
+        }
+    };
+
+    let message = if message.chars().count() > MAX_MESSAGE_LENGTH {
+        let mut truncated: String = message.chars().take(MAX_MESSAGE_LENGTH - 1).collect();
+        truncated.push('…');
+        truncated
+    } else {
+        message.to_string()
+    };
+
+    channel_id.say(http, message).await?;
+
+    Ok(())
+}
+
+/// Checks whether the member invoking a command is allowed to configure the bot
+pub fn is_admin(member: Option<&Member>) -> bool {
+    member
+        .and_then(|m| m.permissions)
+        .map(|p| p.administrator() || p.manage_guild())
+        .unwrap_or(false)
+}
@@ -1,76 +1,383 @@
-use super::{Response, Result};
+use super::{notify_admins, Response, Result};
 use crate::{
-    bot::run::{LichessClientContainer, PoolContainer, RoleManagerContainer},
-    lichess::Format,
-    models::User,
+    bot::{
+        member_profile::MemberProfile,
+        role_manager::RoleManager,
+        run::{
+            ChesscomClientContainer, LichessClientContainer, PoolContainer, RoleManagerContainer,
+        },
+    },
+    db::Pool,
+    models::{Guild, Hysteresis, User},
+    ratings::{Rating, RatingProvider},
 };
-use serenity::{builder::CreateEmbed, model::prelude::*, prelude::*};
-use strum::IntoEnumIterator;
+use reqwest::header::{HeaderMap, HeaderValue};
+use serde_json::json;
+use serenity::{
+    builder::CreateEmbed,
+    http::{request::RequestBuilder, routing::RouteInfo, Http, HttpError, StatusCode},
+    prelude::*,
+};
+use std::time::Duration;
+use tokio::time::sleep;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-async fn update_rating_roles(
-    ctx: &Context,
+// Discord refuses embeds with more than 25 fields, three of which are kept for role changes
+const MAX_RATING_FIELDS: usize = 22;
+
+// Discord truncates audit log reasons longer than 512 characters
+const MAX_REASON_LENGTH: usize = 512;
+
+// Audit log reason for members whose rating roles were stripped
+const FLAGGED_REASON: &str = "Liro rating update: linked account closed or marked for violating \
+    the terms of service";
+
+// Attempts made to edit the roles of a member before giving up
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Tells whether retrying a failed Discord request might succeed
+fn is_transient(error: &SerenityError) -> bool {
+    match error {
+        SerenityError::Http(e) => match e.as_ref() {
+            HttpError::UnsuccessfulRequest(response) => {
+                response.status_code.is_server_error()
+                    || response.status_code == StatusCode::TOO_MANY_REQUESTS
+            }
+            HttpError::Request(e) => e.is_timeout() || e.is_connect(),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Percent-encodes `reason`, as Discord expects audit log reasons to be
+fn encode_reason(reason: &str) -> String {
+    reason
+        .chars()
+        .take(MAX_REASON_LENGTH)
+        .collect::<String>()
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect()
+}
+
+/// Describes the ratings of `profile` for the audit log
+fn audit_reason(profile: &MemberProfile) -> String {
+    let mut ratings: Vec<String> = profile
+        .ratings
+        .iter()
+        .map(|(provider, format, rating)| {
+            format!("{} {} {}", provider.name(), format.key(), rating.rating())
+        })
+        .collect();
+    ratings.sort();
+
+    format!("Liro rating update: {}", ratings.join(", "))
+}
+
+/// Replaces the roles of `discord_id` with `roles` in a single request
+///
+/// `reason` is shown in the audit log of the guild. Transient failures are retried with an
+/// increasing delay.
+async fn edit_member_roles(
+    http: &Http,
+    guild_id: u64,
+    discord_id: u64,
+    roles: &[u64],
+    reason: &str,
+) -> Result<()> {
+    trace!("edit_member_roles() called");
+    let body = serde_json::to_vec(&json!({ "roles": roles })).map_err(SerenityError::from)?;
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "X-Audit-Log-Reason",
+        HeaderValue::from_str(&encode_reason(reason))
+            .map_err(|e| SerenityError::Http(Box::new(HttpError::InvalidHeader(e))))?,
+    );
+
+    let mut attempt = 1;
+    loop {
+        let mut request = RequestBuilder::new(RouteInfo::EditMember {
+            guild_id,
+            user_id: discord_id,
+        });
+        request.body(Some(&body)).headers(Some(headers.clone()));
+
+        match http.request(request.build()).await {
+            Ok(_) => return Ok(()),
+            Err(why) if attempt < MAX_ATTEMPTS && is_transient(&why) => {
+                warn!(
+                    "Could not edit roles of discord_id={} in guild_id={} (attempt {}): {}",
+                    discord_id, guild_id, attempt, why
+                );
+                sleep(Duration::from_millis(500 << attempt)).await;
+                attempt += 1;
+            }
+            Err(why) => {
+                error!(
+                    "Could not edit roles of discord_id={} in guild_id={}: {}",
+                    discord_id, guild_id, why
+                );
+                return Err(why.into());
+            }
+        }
+    }
+}
+
+/// Rating roles matching `profile` in `guild`, followed by the ones it is in the grace band of
+pub fn matching_roles(
+    rm: &RoleManager,
+    guild: &Guild,
+    profile: &MemberProfile,
+) -> (Vec<u64>, Vec<u64>) {
+    trace!("matching_roles() called");
+    let guild_id = guild.id();
+    let matched_roles =
+        rm.find_rating_range_roles(guild_id, profile, guild.get_policy(), guild.get_formats());
+    let grace_roles = rm.find_grace_roles(
+        guild_id,
+        profile,
+        guild.get_policy(),
+        guild.get_formats(),
+        guild.get_hysteresis().grace,
+    );
+
+    (matched_roles, grace_roles)
+}
+
+/// Splits the roles a member should have and shouldn't have into the ones to add to and remove
+/// from `current`
+pub fn diff_roles(
+    current: &[u64],
+    rating_roles: Vec<u64>,
+    removeable_roles: Vec<u64>,
+) -> (Vec<u64>, Vec<u64>) {
+    let added = rating_roles
+        .into_iter()
+        .filter(|r| !current.contains(r))
+        .collect();
+    let removed = removeable_roles
+        .into_iter()
+        .filter(|r| current.contains(r))
+        .collect();
+
+    (added, removed)
+}
+
+/// Gives `rating_roles` to the member and takes `removeable_roles` away from them
+///
+/// The new set of roles is applied at once, so that the member never ends up with only part of
+/// the changes. Returns the roles actually added and removed.
+pub async fn update_rating_roles(
+    http: &Http,
     guild_id: u64,
     discord_id: u64,
     rating_roles: Vec<u64>,
     removeable_roles: Vec<u64>,
+    reason: &str,
 ) -> Result<(Vec<u64>, Vec<u64>)> {
     trace!("update_rating_roles() called");
-    let member = ctx
-        .http
-        .get_member(guild_id, discord_id)
-        .await
-        .map_err(|e| {
-            error!(
-                "Could not retrieve user information for discord_id={} in guild_id={}: {}",
-                discord_id, guild_id, e
-            );
+    let member = http.get_member(guild_id, discord_id).await.map_err(|e| {
+        error!(
+            "Could not retrieve user information for discord_id={} in guild_id={}: {}",
+            discord_id, guild_id, e
+        );
+        e
+    })?;
+
+    let current: Vec<u64> = member.roles.iter().map(|r| *r.as_u64()).collect();
+    let (added, removed) = diff_roles(&current, rating_roles, removeable_roles);
+
+    if added.is_empty() && removed.is_empty() {
+        debug!("Roles of discord_id={} are up to date", discord_id);
+        return Ok((added, removed));
+    }
+
+    let roles: Vec<u64> = current
+        .iter()
+        .filter(|r| !removed.contains(r))
+        .chain(added.iter())
+        .copied()
+        .collect();
+
+    edit_member_roles(http, guild_id, discord_id, &roles, reason).await?;
+    debug!(
+        "Added role_ids={:?} and removed role_ids={:?} for discord_id={}",
+        added, removed, discord_id
+    );
+
+    Ok((added, removed))
+}
+
+/// Tells the admins of `guild_id` that an account of `user` was just flagged
+///
+/// Failures are only logged, as the roles of the member are already up to date.
+async fn notify_flagged(http: &Http, pool: &Pool, guild_id: u64, user: &User, stripped: bool) {
+    trace!("notify_flagged() called");
+    let accounts: Vec<String> = user
+        .get_accounts()
+        .iter()
+        .filter(|a| a.is_flagged())
+        .map(|a| format!("`{}`", a))
+        .collect();
+    let roles = if stripped {
+        "so I removed their rating roles"
+    } else {
+        "their rating roles were left alone"
+    };
+    let message = format!(
+        "<@{}> linked {}, which lichess closed or marked for violating its terms of service, {}.",
+        user.discord_id(),
+        accounts.join(", "),
+        roles
+    );
+
+    if let Err(e) = notify_admins(http, pool, guild_id, &message).await {
+        warn!(
+            "Could not notify the admins of guild_id={} about discord_id={}: {}",
+            guild_id,
+            user.discord_id(),
             e
-        })?;
-
-    let mut added = vec![];
-    let mut removed = vec![];
-
-    for role_id in rating_roles {
-        if member.roles.contains(&RoleId(role_id)) {
-            debug!("User already has role_id={}", role_id)
-        } else {
-            debug!("User is missing role_id={}", role_id);
-            ctx.http
-                .add_member_role(guild_id, discord_id, role_id)
-                .await
-                .map_err(|e| {
-                    error!(
-                        "Could not add role_id={} to discord_id={}: {}",
-                        role_id, discord_id, e
-                    );
-                    e
-                })?;
-            added.push(role_id);
-            debug!("Added role_id={} to discord_id={}", role_id, discord_id);
-        }
+        );
     }
+}
 
-    for role_id in removeable_roles {
-        if member.roles.contains(&RoleId(role_id)) {
-            debug!("User has extra role_id={} that should be removed", role_id);
-            ctx.http
-                .remove_member_role(guild_id, discord_id, role_id)
-                .await
-                .map_err(|e| {
-                    error!(
-                        "Could not remove role_id={} from discord_id={}: {}",
-                        role_id, discord_id, e
-                    );
-                    e
-                })?;
-            removed.push(role_id);
-            debug!("Removed role_id={} from discord_id={}", role_id, discord_id);
-        }
+/// Roles given to and taken away from a member by a sync
+pub struct RoleChanges {
+    pub added: Vec<u64>,
+    pub removed: Vec<u64>,
+    /// Whether some roles the member qualifies for can't be handed out by the bot
+    pub skipped: bool,
+    /// Whether the rating roles of the member were stripped because of a flagged account
+    pub stripped: bool,
+}
+
+/// Refreshes the ratings of `user` from `providers` and gives them the roles they qualify for in
+/// `guild`
+///
+/// Peak ratings and teams fetched less than `max_age` seconds ago are kept, manual updates pass 0
+/// to always refresh them. Members with an account closed or marked for violating the terms of
+/// service are handled as the guild's policy says. The user is marked as synced once their roles
+/// are up to date.
+pub async fn sync_member(
+    http: &Http,
+    pool: &Pool,
+    rm: &RoleManager,
+    providers: &[&dyn RatingProvider],
+    guild: &Guild,
+    user: &mut User,
+    max_age: u64,
+) -> Result<RoleChanges> {
+    trace!("sync_member() called");
+    let guild_id = guild.id();
+    let was_flagged = user.is_flagged();
+    user.update_ratings(pool, providers, max_age).await?;
+
+    if !guild.get_team_roles().is_empty() {
+        user.update_teams(pool, providers, max_age).await?;
     }
 
-    Ok((added, removed))
+    let flagged_policy = guild.get_flagged_policy();
+    let stripped = user.is_flagged() && flagged_policy.strip_roles;
+    let profile = MemberProfile::new(user, guild.get_role_source(), guild.get_policy());
+
+    // Stripped members lose every rating role at once, regardless of the demotion settings, while
+    // their title, patron and team roles still follow their profile
+    let (matched_roles, grace_roles) = matching_roles(rm, guild, &profile);
+    let rating_roles = if stripped {
+        let kept = rm.without_rating_roles(guild_id, matched_roles);
+        user.update_role_states(pool, &kept, &[], Hysteresis::default())
+            .await?
+    } else {
+        user.update_role_states(pool, &matched_roles, &grace_roles, guild.get_hysteresis())
+            .await?
+    };
+    let removeable_roles = rm.other_rating_range_roles(guild_id, &rating_roles);
+
+    // Roles the bot can't hand out are left alone, the admins were told by the preflight
+    let wanted = rating_roles.len();
+    let rating_roles = rm.filter_manageable(guild_id, rating_roles);
+    let removeable_roles = rm.filter_manageable(guild_id, removeable_roles);
+    let skipped = wanted > rating_roles.len();
+
+    let reason = if stripped {
+        FLAGGED_REASON.to_string()
+    } else {
+        audit_reason(&profile)
+    };
+    let (added, removed) = update_rating_roles(
+        http,
+        guild_id,
+        user.discord_id(),
+        rating_roles,
+        removeable_roles,
+        &reason,
+    )
+    .await?;
+    user.mark_synced(pool).await?;
+
+    if user.is_flagged() && !was_flagged && flagged_policy.notify {
+        notify_flagged(http, pool, guild_id, user, stripped).await;
+    }
+
+    Ok(RoleChanges {
+        added,
+        removed,
+        skipped,
+        stripped,
+    })
+}
+
+fn rating_description(old_rating: Option<&Rating>, new_rating: Option<&Rating>) -> String {
+    let description = match (old_rating, new_rating) {
+        (Some(old_rating), Some(new_rating)) if old_rating.rating() == new_rating.rating() => {
+            new_rating.to_string()
+        }
+        (Some(old_rating), Some(new_rating)) if old_rating.rating() < new_rating.rating() => {
+            format!(
+                ":chart_with_upwards_trend: {} -> {}",
+                old_rating, new_rating
+            )
+        }
+        (Some(old_rating), Some(new_rating)) => {
+            format!(
+                ":chart_with_downwards_trend: {} -> {}",
+                old_rating, new_rating
+            )
+        }
+        (None, Some(new_rating)) => {
+            format!(":new: {}", new_rating)
+        }
+        (Some(old_rating), None) => {
+            return format!(":crying_cat_face: ~~{}~~", old_rating);
+        }
+        _ => return "Unrated".to_string(),
+    };
+
+    match new_rating {
+        Some(rating) => {
+            let mut details = format!("{}\n{} games", description, rating.games());
+
+            if let Some(rd) = rating.rd() {
+                details.push_str(&format!(", RD {}", rd));
+            }
+
+            // Providers that don't track progress report none
+            if rating.progress() != 0 {
+                details.push_str(&format!(", progress {:+}", rating.progress()));
+            }
+
+            details
+        }
+        None => description,
+    }
 }
 
 pub async fn update_ratings(ctx: &Context, guild_id: u64, discord_id: u64) -> Result<Response> {
@@ -82,59 +389,88 @@ pub async fn update_ratings(ctx: &Context, guild_id: u64, discord_id: u64) -> Re
     );
 
     let lichess;
+    let chesscom;
     let pool;
     let rm;
     {
         let data = ctx.data.read().await;
 
         lichess = data.get::<LichessClientContainer>().unwrap().clone();
+        chesscom = data.get::<ChesscomClientContainer>().unwrap().clone();
         pool = data.get::<PoolContainer>().unwrap().clone();
         rm = data.get::<RoleManagerContainer>().unwrap().clone();
     }
 
     match User::find(&pool, guild_id, discord_id).await {
         Ok(Some(mut user)) => {
-            let old_ratings = user.get_ratings().clone();
-            let ratings = user.update_ratings(&pool, &lichess).await?.clone();
-
-            let rating_roles = rm.find_rating_range_roles(guild_id, &ratings);
-            let removeable_roles = rm.other_rating_range_roles(guild_id, &rating_roles);
-            let (added, removed) =
-                update_rating_roles(ctx, guild_id, discord_id, rating_roles, removeable_roles)
-                    .await?;
+            let guild = Guild::find_or_create(&pool, guild_id, "").await?;
+            let old_accounts = user.get_accounts().to_vec();
+            let result = sync_member(
+                &ctx.http,
+                &pool,
+                &rm,
+                &[&lichess, &chesscom],
+                &guild,
+                &mut user,
+                0,
+            )
+            .await;
+            let RoleChanges {
+                added,
+                removed,
+                skipped,
+                stripped,
+            } = match result {
+                Ok(changes) => changes,
+                Err(why) => {
+                    return match why.describe() {
+                        Some(message) => {
+                            warn!(
+                                "Could not update ratings of discord_id={}: {}",
+                                discord_id, why
+                            );
+                            Ok(Response::Sentence(message))
+                        }
+                        None => Err(why),
+                    }
+                }
+            };
 
             let mut embed = CreateEmbed {
                 ..Default::default()
             };
 
-            for format in Format::iter() {
-                let old_rating = old_ratings.get(&format);
-                let new_rating = ratings.get(&format);
-                let description = match (old_rating, new_rating) {
-                    (Some(old_rating), Some(new_rating)) if old_rating == new_rating => {
-                        old_rating.to_string()
-                    }
-                    (Some(old_rating), Some(new_rating)) if old_rating < new_rating => {
-                        format!(
-                            ":chart_with_upwards_trend: {} -> {}",
-                            old_rating, new_rating
-                        )
-                    }
-                    (Some(old_rating), Some(new_rating)) if old_rating > new_rating => {
-                        format!(
-                            ":chart_with_downwards_trend: {} -> {}",
-                            old_rating, new_rating
-                        )
-                    }
-                    (None, Some(new_rating)) => {
-                        format!(":new: {}", new_rating)
-                    }
-                    (Some(old_rating), None) => {
-                        format!(":crying_cat_face: ~~{}~~", old_rating)
-                    }
-                    _ => "Unrated (or provisional)".to_string(),
+            let accounts = user.get_accounts();
+            let formats = guild.get_formats();
+            let fields = accounts.iter().flat_map(|account| {
+                account
+                    .provider()
+                    .formats()
+                    .into_iter()
+                    .filter(move |f| formats.contains(f))
+                    .map(move |f| (account, f))
+            });
+
+            for (account, format) in fields.take(MAX_RATING_FIELDS) {
+                let old_rating = old_accounts
+                    .iter()
+                    .find(|a| {
+                        a.provider() == account.provider() && a.username() == account.username()
+                    })
+                    .and_then(|a| a.get_ratings().get(&format));
+                let new_rating = account.get_ratings().get(&format);
+                let mut description = rating_description(old_rating, new_rating);
+
+                if let Some(Err(why)) = new_rating.map(|r| guild.get_policy().check(format, r)) {
+                    description.push_str(&format!("\n:warning: Not counted: {}", why));
+                }
+
+                let name = if accounts.len() > 1 {
+                    format!("{} ({})", format, account)
+                } else {
+                    format.to_string()
                 };
-                embed.field(format.to_string(), description, true);
+                embed.field(name, description, true);
             }
 
             if !added.is_empty() {
@@ -147,20 +483,49 @@ pub async fn update_ratings(ctx: &Context, guild_id: u64, discord_id: u64) -> Re
                 embed.field("Roles removed", role_names.join(", "), false);
             }
 
-            embed
-                .description(format!(
-                    "Ratings for [{}](https://lichess.org/@/{}) from \
-                            [lichess](https://lichess.org).",
-                    user.get_lichess_username(),
-                    user.get_lichess_username()
+            if stripped {
+                embed.field(
+                    "Roles stripped",
+                    "A linked account was closed or marked for violating the terms of service, so \
+                    you can't hold rating roles on this server.",
+                    false,
+                );
+            } else if removed.is_empty() && skipped {
+                embed.field(
+                    "Roles not updated",
+                    "I'm not allowed to give you some roles on this server. The admins have been \
+                    told how to fix it.",
+                    false,
+                );
+            }
+
+            let description = accounts
+                .iter()
+                .map(|account| {
+                    let provider = account.provider();
+                    let primary = if account.is_primary() && accounts.len() > 1 {
+                        " (primary)"
+                    } else {
+                        ""
+                    };
+                    format!(
+                        "Ratings for [{}]({}) from [{}]({}){}.",
+                        account.username(),
+                        provider.profile_url(account.username()),
+                        provider.name(),
+                        provider.url(),
+                        primary
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            embed.description(description).footer(|f| {
+                f.text(format!(
+                    "Liro version {}. Provisional ratings are marked with a ?.",
+                    VERSION
                 ))
-                .footer(|f| {
-                    f.text(format!(
-                        "Liro version {}. Please note that this bot only cares about the \
-                                four rating formats shown above. Provisional ratings are ignored.",
-                        VERSION
-                    ))
-                });
+            });
 
             Ok(Response::Embed(embed))
         }
@@ -177,3 +542,23 @@ pub async fn update_ratings(ctx: &Context, guild_id: u64, discord_id: u64) -> Re
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn audit_reasons_are_percent_encoded_and_truncated() {
+        assert_eq!(encode_reason("Liro: 1800+"), "Liro%3A%201800%2B");
+        assert_eq!(encode_reason("é"), "%C3%A9");
+        assert_eq!(encode_reason(&"a".repeat(600)).len(), MAX_REASON_LENGTH);
+    }
+
+    #[test]
+    fn diff_roles_only_changes_what_differs() {
+        let (added, removed) = diff_roles(&[1, 2, 3], vec![1, 4], vec![2, 5]);
+
+        assert_eq!(added, vec![4]);
+        assert_eq!(removed, vec![2]);
+    }
+}
@@ -0,0 +1,710 @@
+use super::{
+    get_integer_option, get_role_option, get_string_option, get_user_option, notify_admins,
+    rating_update::{diff_roles, matching_roles},
+    Response, Result, MAX_MESSAGE_LENGTH,
+};
+use crate::{
+    bot::{
+        member_profile::MemberProfile,
+        preflight::{check_permissions, ManagedRole, PreflightIssue},
+        rating_range::Target,
+        role_ladder::{ladder, ladder_positions},
+        role_manager::RoleManager,
+        role_rule::RoleRule,
+        run::{PoolContainer, RoleManagerContainer},
+    },
+    models::{Guild, Hysteresis, User},
+};
+use serde_json::json;
+use serenity::{
+    http::{request::RequestBuilder, routing::RouteInfo},
+    model::{
+        guild::{Member, Role},
+        id::GuildId,
+        interactions::application_command::ApplicationCommandInteractionDataOption,
+    },
+    prelude::*,
+};
+use std::collections::HashMap;
+
+/// Updates the rule managing `role_id` after its bindings in `guild` changed
+///
+/// Explicit bindings come first, then lichess team mappings, and the role name is only used
+/// when the role is bound to neither.
+pub async fn reload_role(
+    ctx: &Context,
+    rm: &mut RoleManager,
+    guild: &Guild,
+    role_id: u64,
+) -> Result<()> {
+    trace!("reload_role() called");
+    let guild_id = guild.id();
+    rm.remove_role(guild_id, role_id);
+
+    if let Some(rule) = guild.get_role_bindings().get(&role_id) {
+        if let Ok(rule) = RoleRule::parse_binding(rule) {
+            rm.add_role(guild_id, role_id, rule);
+        }
+        return Ok(());
+    }
+
+    if let Some((team_id, _)) = guild.get_team_roles().iter().find(|(_, &r)| r == role_id) {
+        rm.add_role(guild_id, role_id, RoleRule::Team(team_id.clone()));
+        return Ok(());
+    }
+
+    if let Some(role) = ctx
+        .http
+        .get_guild_roles(guild_id)
+        .await?
+        .iter()
+        .find(|r| *r.id.as_u64() == role_id)
+    {
+        if let Ok(rule) = role.name.parse::<RoleRule>() {
+            rm.add_role(guild_id, role_id, rule);
+        }
+    }
+
+    Ok(())
+}
+
+async fn bind(
+    ctx: &Context,
+    guild_id: u64,
+    role_id: Option<u64>,
+    rule: Option<&str>,
+) -> Result<Response> {
+    trace!("bind() called");
+    let (role_id, rule) = match (role_id, rule) {
+        (Some(role_id), Some(rule)) => (role_id, rule),
+        _ => {
+            return Ok(Response::PrivateSentence(
+                "Please give me a role and the rule to bind it to.".to_string(),
+            ))
+        }
+    };
+
+    let parsed = match RoleRule::parse_binding(rule.trim()) {
+        Ok(parsed) => parsed,
+        Err(why) => {
+            return Ok(Response::PrivateSentence(format!(
+                "I don't understand the rule `{}`: {}. Rules are written like the role names I \
+                recognise, such as `1800+ blitz`, `U1200 rapid chesscom`, `peak 2000+ bullet` or \
+                `GM`, or combine conditions like \
+                `blitz >= 1500 and rapid >= 1500 and games >= 100`.",
+                rule, why
+            )))
+        }
+    };
+
+    let name = match parsed.get_name() {
+        Some(name) => name,
+        None => {
+            return Ok(Response::PrivateSentence(format!(
+                "The rule `{}` can't be bound to a role.",
+                rule
+            )))
+        }
+    };
+
+    let pool;
+    let mut rm;
+    {
+        let data = ctx.data.read().await;
+        pool = data.get::<PoolContainer>().unwrap().clone();
+        rm = data.get::<RoleManagerContainer>().unwrap().clone();
+    }
+
+    let mut guild = Guild::find_or_create(&pool, guild_id, "").await?;
+
+    info!(
+        "Binding role_id={} to {} in guild_id={}",
+        role_id, name, guild_id
+    );
+    guild.bind_role(&pool, role_id, &name).await?;
+    reload_role(ctx, &mut rm, &guild, role_id).await?;
+
+    Ok(Response::PrivateSentence(format!(
+        "<@&{}> is now given for `{}`, whatever the role is called.",
+        role_id, name
+    )))
+}
+
+async fn unbind(ctx: &Context, guild_id: u64, role_id: Option<u64>) -> Result<Response> {
+    trace!("unbind() called");
+    let role_id = match role_id {
+        Some(role_id) => role_id,
+        None => {
+            return Ok(Response::PrivateSentence(
+                "Please tell me which role to unbind.".to_string(),
+            ))
+        }
+    };
+
+    let pool;
+    let mut rm;
+    {
+        let data = ctx.data.read().await;
+        pool = data.get::<PoolContainer>().unwrap().clone();
+        rm = data.get::<RoleManagerContainer>().unwrap().clone();
+    }
+
+    let mut guild = Guild::find_or_create(&pool, guild_id, "").await?;
+
+    match guild.unbind_role(&pool, role_id).await? {
+        Some(rule) => {
+            info!(
+                "Unbinding role_id={} from {} in guild_id={}",
+                role_id, rule, guild_id
+            );
+            reload_role(ctx, &mut rm, &guild, role_id).await?;
+
+            Ok(Response::PrivateSentence(format!(
+                "<@&{}> isn't bound to `{}` anymore. Unless it is mapped to a lichess team, it is \
+                now only managed if its name is one I recognise.",
+                role_id, rule
+            )))
+        }
+        None => Ok(Response::PrivateSentence(format!(
+            "<@&{}> isn't bound to any rule.",
+            role_id
+        ))),
+    }
+}
+
+async fn list(ctx: &Context, guild_id: u64) -> Result<Response> {
+    trace!("list() called");
+    let pool;
+    let rm;
+    {
+        let data = ctx.data.read().await;
+        pool = data.get::<PoolContainer>().unwrap().clone();
+        rm = data.get::<RoleManagerContainer>().unwrap().clone();
+    }
+
+    let guild = Guild::find_or_create(&pool, guild_id, "").await?;
+
+    let mut roles = rm.get_roles(guild_id);
+    if roles.is_empty() {
+        return Ok(Response::PrivateSentence(
+            "I don't manage any role on this server yet.".to_string(),
+        ));
+    }
+
+    roles.sort_by_key(|(_, rule)| rule.get_name());
+
+    let lines: Vec<String> = roles
+        .iter()
+        .map(|(role_id, rule)| {
+            let origin = if guild.is_bound(*role_id) {
+                "bound"
+            } else if guild.get_generated_roles().contains(role_id) {
+                "generated"
+            } else {
+                "from its name"
+            };
+            format!(
+                "<@&{}>: `{}` ({})",
+                role_id,
+                rule.get_name().unwrap_or_default(),
+                origin
+            )
+        })
+        .collect();
+
+    Ok(Response::PrivateSentence(format!(
+        "Roles managed on this server:\n{}",
+        lines.join("\n")
+    )))
+}
+
+async fn check(ctx: &Context, guild_id: u64) -> Result<Response> {
+    trace!("check() called");
+    let mut rm;
+    {
+        let data = ctx.data.read().await;
+        rm = data.get::<RoleManagerContainer>().unwrap().clone();
+    }
+
+    let preflight_issues = preflight(ctx, &mut rm, guild_id).await?;
+    let issues = rm.check_rating_ranges(guild_id);
+    if issues.is_empty() && preflight_issues.is_empty() {
+        return Ok(Response::PrivateSentence(
+            "I didn't find any overlapping, missing or unreachable rating range, and I can hand \
+            out every role I manage."
+                .to_string(),
+        ));
+    }
+
+    let lines: Vec<String> = preflight_issues
+        .iter()
+        .map(|i| format!("- {}", i))
+        .chain(issues.iter().map(|i| format!("- {}", i)))
+        .collect();
+
+    Ok(Response::PrivateSentence(format!(
+        "I found the following issues with the roles I manage on this server:\n{}",
+        lines.join("\n")
+    )))
+}
+
+/// Fetches the member of the bot in `guild_id`, along with every role of the guild
+async fn bot_member_and_roles(ctx: &Context, guild_id: u64) -> Result<(Member, Vec<Role>)> {
+    trace!("bot_member_and_roles() called");
+    let bot = ctx.http.get_current_user().await?;
+    let member = ctx.http.get_member(guild_id, *bot.id.as_u64()).await?;
+    let roles = ctx.http.get_guild_roles(guild_id).await?;
+
+    Ok((member, roles))
+}
+
+fn highest_position(member: &Member, roles: &[Role]) -> Option<i64> {
+    roles
+        .iter()
+        .filter(|r| member.roles.contains(&r.id))
+        .map(|r| r.position)
+        .max()
+}
+
+/// Checks whether the bot can hand out the roles it manages in `guild_id`
+///
+/// The issues found are recorded in `rm`, so that rating updates skip the roles concerned, and
+/// the admins are told about them whenever they change.
+pub async fn preflight(
+    ctx: &Context,
+    rm: &mut RoleManager,
+    guild_id: u64,
+) -> Result<Vec<PreflightIssue>> {
+    trace!("preflight() called");
+    let (member, roles) = bot_member_and_roles(ctx, guild_id).await?;
+
+    // The @everyone role shares its ID with the guild and isn't listed in the member's roles
+    let manage_roles = roles
+        .iter()
+        .filter(|r| member.roles.contains(&r.id) || *r.id.as_u64() == guild_id)
+        .any(|r| r.permissions.administrator() || r.permissions.manage_roles());
+
+    let managed: Vec<ManagedRole> = rm
+        .get_roles(guild_id)
+        .into_iter()
+        .filter_map(|(role_id, _)| roles.iter().find(|r| *r.id.as_u64() == role_id))
+        .map(|r| ManagedRole {
+            id: *r.id.as_u64(),
+            name: r.name.clone(),
+            position: r.position,
+            integration: r.managed,
+        })
+        .collect();
+
+    let issues = check_permissions(manage_roles, highest_position(&member, &roles), &managed);
+
+    if rm.set_preflight_issues(guild_id, issues.clone()) {
+        if issues.is_empty() {
+            info!("Preflight checks of guild_id={} passed", guild_id);
+        } else {
+            for issue in &issues {
+                warn!("Preflight check of guild_id={} failed: {}", guild_id, issue);
+            }
+
+            let lines: Vec<String> = issues.iter().map(|i| format!("- {}", i)).collect();
+            let pool;
+            {
+                let data = ctx.data.read().await;
+                pool = data.get::<PoolContainer>().unwrap().clone();
+            }
+
+            let message = format!(
+                "I can't hand out some of the roles I manage on this server:\n{}",
+                lines.join("\n")
+            );
+            if let Err(e) = notify_admins(&ctx.http, &pool, guild_id, &message).await {
+                warn!(
+                    "Could not notify the admins of guild_id={} about preflight issues: {}",
+                    guild_id, e
+                );
+            }
+        }
+    }
+
+    Ok(issues)
+}
+
+async fn generate(
+    ctx: &Context,
+    guild_id: u64,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Result<Response> {
+    trace!("generate() called");
+    let target = match get_string_option(options, "format").map(|f| f.trim().to_lowercase()) {
+        Some(format) => match format.parse::<Target>() {
+            Ok(target) => target,
+            Err(_) => {
+                return Ok(Response::PrivateSentence(format!(
+                    "I don't know the format `{}`.",
+                    format
+                )))
+            }
+        },
+        None => {
+            return Ok(Response::PrivateSentence(
+                "Please tell me which format to generate roles for.".to_string(),
+            ))
+        }
+    };
+
+    let integer = |name| {
+        get_integer_option(options, name).map(|v| v.clamp(i16::MIN as i64, i16::MAX as i64) as i16)
+    };
+    let (step, from, to) = match (integer("step"), integer("from"), integer("to")) {
+        (Some(step), Some(from), Some(to)) => (step, from, to),
+        _ => {
+            return Ok(Response::PrivateSentence(
+                "Please give me the step, lowest and highest ratings of the ladder.".to_string(),
+            ))
+        }
+    };
+
+    let roles = match ladder(target, step, from, to) {
+        Ok(roles) => roles,
+        Err(why) => {
+            return Ok(Response::PrivateSentence(format!(
+                "I can't generate these roles: {}.",
+                why
+            )))
+        }
+    };
+
+    let pool;
+    let mut rm;
+    {
+        let data = ctx.data.read().await;
+        pool = data.get::<PoolContainer>().unwrap().clone();
+        rm = data.get::<RoleManagerContainer>().unwrap().clone();
+    }
+
+    let mut guild = Guild::find_or_create(&pool, guild_id, "").await?;
+
+    info!(
+        "Generating {} rating roles for {} in guild_id={}",
+        roles.len(),
+        target.key(),
+        guild_id
+    );
+
+    let mut created = vec![];
+    let mut failure = None;
+    for role in &roles {
+        match GuildId(guild_id)
+            .create_role(&ctx.http, |r| {
+                r.name(&role.name)
+                    .colour(role.colour)
+                    .hoist(false)
+                    .mentionable(false)
+            })
+            .await
+        {
+            Ok(created_role) => {
+                rm.add_role(guild_id, *created_role.id.as_u64(), role.range.clone());
+                created.push(*created_role.id.as_u64());
+            }
+            Err(why) => {
+                failure = Some(why);
+                break;
+            }
+        }
+    }
+
+    // Roles are saved even if some of them couldn't be created, so they can still be removed
+    guild.add_generated_roles(&pool, &created).await?;
+
+    if let Some(why) = failure {
+        return Err(why.into());
+    }
+
+    // New roles are created at the bottom, and moved right under the bot's own role in a single
+    // request, which leaves them sorted by rating
+    let (member, roles) = bot_member_and_roles(ctx, guild_id).await?;
+    if let Some(position) = highest_position(&member, &roles) {
+        let roles: Vec<(u64, i64)> = roles
+            .iter()
+            .filter(|r| *r.id.as_u64() != guild_id)
+            .map(|r| (*r.id.as_u64(), r.position))
+            .collect();
+        let positions: Vec<_> = ladder_positions(&roles, &created, position)
+            .into_iter()
+            .map(|(id, position)| json!({ "id": id, "position": position }))
+            .collect();
+        let body = serde_json::to_vec(&positions).map_err(SerenityError::from)?;
+
+        let mut request = RequestBuilder::new(RouteInfo::EditRolePosition { guild_id });
+        request.body(Some(&body));
+        ctx.http.request(request.build()).await?;
+    }
+
+    let mentions: Vec<String> = created.iter().map(|id| format!("<@&{}>", id)).collect();
+
+    Ok(Response::PrivateSentence(format!(
+        "I created the following roles: {}. Use `/roles remove-generated` to delete them.",
+        mentions.join(", ")
+    )))
+}
+
+async fn remove_generated(ctx: &Context, guild_id: u64) -> Result<Response> {
+    trace!("remove_generated() called");
+    let pool;
+    let mut rm;
+    {
+        let data = ctx.data.read().await;
+        pool = data.get::<PoolContainer>().unwrap().clone();
+        rm = data.get::<RoleManagerContainer>().unwrap().clone();
+    }
+
+    let mut guild = Guild::find_or_create(&pool, guild_id, "").await?;
+    let role_ids = guild.take_generated_roles(&pool).await?;
+
+    if role_ids.is_empty() {
+        return Ok(Response::PrivateSentence(
+            "I didn't generate any role on this server.".to_string(),
+        ));
+    }
+
+    info!(
+        "Removing {} generated roles from guild_id={}",
+        role_ids.len(),
+        guild_id
+    );
+
+    let mut removed = 0;
+    let mut kept = vec![];
+    for role_id in role_ids {
+        match ctx.http.delete_role(guild_id, role_id).await {
+            Ok(_) => {
+                rm.remove_role(guild_id, role_id);
+                removed += 1;
+            }
+            Err(why) => {
+                warn!(
+                    "Could not delete generated role_id={} in guild_id={}: {}",
+                    role_id, guild_id, why
+                );
+                kept.push(role_id);
+            }
+        }
+    }
+
+    // Roles still on the server stay generated, so that a later attempt can delete them
+    if kept.is_empty() {
+        return Ok(Response::PrivateSentence(format!(
+            "I deleted {} generated roles.",
+            removed
+        )));
+    }
+
+    guild.add_generated_roles(&pool, &kept).await?;
+    Ok(Response::PrivateSentence(format!(
+        "I deleted {} generated roles, but couldn't delete {} others. Please try again later.",
+        removed,
+        kept.len()
+    )))
+}
+
+// Most members Discord lists in a single request
+const MEMBER_PAGE_SIZE: u64 = 1000;
+
+/// Describes the roles `user` would gain and lose on their next rating update, given their
+/// `current` roles
+///
+/// Returns `None` when their roles are up to date.
+fn preview_member(rm: &RoleManager, guild: &Guild, user: &User, current: &[u64]) -> Option<String> {
+    trace!("preview_member() called");
+    let guild_id = guild.id();
+
+    // Flagged members are stripped of every rating role, like a sync would
+    let profile = MemberProfile::new(user, guild.get_role_source(), guild.get_policy());
+    let (matched_roles, grace_roles) = matching_roles(rm, guild, &profile);
+    let rating_roles = if user.is_flagged() && guild.get_flagged_policy().strip_roles {
+        let kept = rm.without_rating_roles(guild_id, matched_roles);
+        user.preview_role_states(&kept, &[], Hysteresis::default())
+    } else {
+        user.preview_role_states(&matched_roles, &grace_roles, guild.get_hysteresis())
+    };
+    let removeable_roles = rm.other_rating_range_roles(guild_id, &rating_roles);
+
+    let (added, removed) = diff_roles(
+        current,
+        rm.filter_manageable(guild_id, rating_roles),
+        rm.filter_manageable(guild_id, removeable_roles),
+    );
+
+    let mut changes = Vec::new();
+    if !added.is_empty() {
+        changes.push(format!(
+            "gains `{}`",
+            rm.get_rating_role_names(guild_id, &added).join("`, `")
+        ));
+    }
+    if !removed.is_empty() {
+        changes.push(format!(
+            "loses `{}`",
+            rm.get_rating_role_names(guild_id, &removed).join("`, `")
+        ));
+    }
+
+    if changes.is_empty() {
+        None
+    } else {
+        Some(format!(
+            "<@{}> {}",
+            user.discord_id(),
+            changes.join(" and ")
+        ))
+    }
+}
+
+/// Current roles of the members of `guild_id`, by Discord ID
+///
+/// Members are listed by pages rather than one at a time, so that whole guilds are previewed in a
+/// handful of requests.
+async fn member_roles(ctx: &Context, guild_id: u64) -> Result<HashMap<u64, Vec<u64>>> {
+    trace!("member_roles() called");
+    let mut roles = HashMap::new();
+    let mut after = None;
+
+    loop {
+        let members = ctx
+            .http
+            .get_guild_members(guild_id, Some(MEMBER_PAGE_SIZE), after)
+            .await?;
+        let count = members.len();
+
+        for member in members {
+            let discord_id = *member.user.id.as_u64();
+            roles.insert(
+                discord_id,
+                member.roles.iter().map(|r| *r.as_u64()).collect(),
+            );
+            after = Some(discord_id);
+        }
+
+        if count < MEMBER_PAGE_SIZE as usize {
+            return Ok(roles);
+        }
+    }
+}
+
+/// Shows the role changes the next rating update would make, without touching any role
+///
+/// Only `discord_id` is previewed when given, every linked member of the guild otherwise. Ratings
+/// aren't refreshed, the ones last retrieved are used.
+async fn preview(ctx: &Context, guild_id: u64, discord_id: Option<u64>) -> Result<Response> {
+    trace!("preview() called");
+    let pool;
+    let rm;
+    {
+        let data = ctx.data.read().await;
+        pool = data.get::<PoolContainer>().unwrap().clone();
+        rm = data.get::<RoleManagerContainer>().unwrap().clone();
+    }
+
+    let guild = Guild::find_or_create(&pool, guild_id, "").await?;
+    let users = match discord_id {
+        Some(discord_id) => match User::find(&pool, guild_id, discord_id).await? {
+            Some(user) => vec![user],
+            None => {
+                return Ok(Response::PrivateSentence(format!(
+                    "<@{}> hasn't linked any account.",
+                    discord_id
+                )))
+            }
+        },
+        None => User::fetch_all(&pool, guild_id).await?,
+    };
+
+    let roles = match discord_id {
+        Some(discord_id) => match ctx.http.get_member(guild_id, discord_id).await {
+            Ok(member) => {
+                let current = member.roles.iter().map(|r| *r.as_u64()).collect();
+                HashMap::from([(discord_id, current)])
+            }
+            Err(why) => {
+                debug!(
+                    "Skipping discord_id={} in guild_id={}: {}",
+                    discord_id, guild_id, why
+                );
+                HashMap::new()
+            }
+        },
+        None => member_roles(ctx, guild_id).await?,
+    };
+
+    // Members who left the guild are skipped
+    let lines: Vec<String> = users
+        .iter()
+        .filter_map(|user| {
+            let current = roles.get(&user.discord_id())?;
+            preview_member(&rm, &guild, user, current)
+        })
+        .collect();
+
+    if lines.is_empty() {
+        return Ok(Response::PrivateSentence(match discord_id {
+            Some(discord_id) => format!("The roles of <@{}> are up to date.", discord_id),
+            None => "The roles of every linked member are up to date.".to_string(),
+        }));
+    }
+
+    let mut message = format!(
+        "The next rating update would change the roles of {} out of {} linked members:",
+        lines.len(),
+        users.len()
+    );
+    for (i, line) in lines.iter().enumerate() {
+        let more = format!("\n…and {} more.", lines.len() - i);
+        if message.len() + line.len() + more.len() + 1 > MAX_MESSAGE_LENGTH {
+            message.push_str(&more);
+            break;
+        }
+        message.push('\n');
+        message.push_str(line);
+    }
+
+    Ok(Response::PrivateSentence(message))
+}
+
+/// Handles the `/roles` admin command and its subcommands
+pub async fn roles(
+    ctx: &Context,
+    guild_id: u64,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Result<Response> {
+    trace!("roles() called");
+    let subcommand = match options.first() {
+        Some(subcommand) => subcommand,
+        None => {
+            return Ok(Response::PrivateSentence(
+                "Please pick something to do with roles.".to_string(),
+            ))
+        }
+    };
+
+    match subcommand.name.as_str() {
+        "bind" => {
+            let role_id = get_role_option(&subcommand.options, "role");
+            let rule = get_string_option(&subcommand.options, "rule");
+            bind(ctx, guild_id, role_id, rule).await
+        }
+        "unbind" => {
+            let role_id = get_role_option(&subcommand.options, "role");
+            unbind(ctx, guild_id, role_id).await
+        }
+        "list" => list(ctx, guild_id).await,
+        "check" => check(ctx, guild_id).await,
+        "generate" => generate(ctx, guild_id, &subcommand.options).await,
+        "remove-generated" => remove_generated(ctx, guild_id).await,
+        "preview" => {
+            let discord_id = get_user_option(&subcommand.options, "member");
+            preview(ctx, guild_id, discord_id).await
+        }
+        _ => unreachable!(),
+    }
+}
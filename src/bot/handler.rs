@@ -2,18 +2,24 @@ use super::run::{PoolContainer, RoleManagerContainer};
 use crate::{
     bot::{
         commands::{
-            account::{link, unlink},
+            account::{link, primary, unlink, verify},
+            config::config,
+            get_string_option, is_admin,
             rating_update::update_ratings,
-            Response as CommandResponse,
+            roles::{preflight, roles},
+            Error as CommandError, Response as CommandResponse,
         },
-        rating_range::RatingRange,
+        role_manager::RoleManager,
+        role_rule::RoleRule,
     },
     models,
 };
 use serenity::{
     async_trait,
     model::{
-        interactions::application_command::ApplicationCommand,
+        interactions::application_command::{
+            ApplicationCommand, ApplicationCommandInteraction, ApplicationCommandOptionType,
+        },
         {gateway::Ready, guild::Guild, prelude::*},
     },
     prelude::*,
@@ -21,6 +27,58 @@ use serenity::{
 
 pub struct Handler;
 
+fn log_range_issues(role_manager: &RoleManager, guild_id: u64) {
+    trace!("log_range_issues() called");
+    for issue in role_manager.check_rating_ranges(guild_id) {
+        warn!("Rating roles of guild_id={}: {}", guild_id, issue);
+    }
+}
+
+async fn run_preflight(ctx: &Context, role_manager: &mut RoleManager, guild_id: u64) {
+    trace!("run_preflight() called");
+    if let Err(e) = preflight(ctx, role_manager, guild_id).await {
+        error!(
+            "Unable to run preflight checks for guild_id={}: {}",
+            guild_id, e
+        );
+    }
+}
+
+/// Tells whether `command` can take longer than the 3 seconds Discord waits for an answer
+///
+/// Such commands are deferred, and their answer replaces the "thinking" message once ready.
+/// Anything fetching ratings qualifies, lichess and chess.com can be slow to answer.
+fn is_slow(command: &ApplicationCommandInteraction) -> bool {
+    let subcommand = command.data.options.first().map(|o| o.name.as_str());
+    match command.data.name.as_str() {
+        "rating" | "verify" => true,
+        "roles" => matches!(
+            subcommand,
+            Some("generate") | Some("preview") | Some("remove-generated")
+        ),
+        _ => false,
+    }
+}
+
+/// Tells whether the answer to a deferred `command` is only shown to the member who ran it
+fn defers_privately(command: &ApplicationCommandInteraction) -> bool {
+    command.data.name == "roles"
+}
+
+/// Logs the failure of a command, and explains it to the member who ran it
+fn error_message(why: &CommandError) -> String {
+    match why.describe() {
+        Some(description) => {
+            warn!("Error handling command: {}", why);
+            description
+        }
+        None => {
+            error!("Error handling command: {}", why);
+            "Internal bot error. @teotwaki, I'm scared.".to_string()
+        }
+    }
+}
+
 #[async_trait]
 impl EventHandler for Handler {
     async fn guild_create(&self, ctx: Context, guild: Guild) {
@@ -28,10 +86,16 @@ impl EventHandler for Handler {
         let data = ctx.data.read().await;
 
         let guild_id = *guild.id.as_u64();
+        let team_roles;
+        let role_bindings;
         {
             let pool = data.get::<PoolContainer>().unwrap().clone();
-            match models::Guild::new(&pool, guild_id, &guild.name).await {
-                Ok(guild) => info!("Joining new {}", guild),
+            match models::Guild::find_or_create(&pool, guild_id, &guild.name).await {
+                Ok(guild) => {
+                    info!("Joining {}", guild);
+                    team_roles = guild.get_team_roles().clone();
+                    role_bindings = guild.get_role_bindings().clone();
+                }
                 Err(e) => {
                     error!("Unable to save guild: {}", e);
                     return;
@@ -40,21 +104,78 @@ impl EventHandler for Handler {
         }
 
         let mut role_manager = data.get::<RoleManagerContainer>().unwrap().clone();
+
+        // Role names are only used for roles the admins didn't bind explicitly
         for (role_id, role) in &guild.roles {
             let role_id = *role_id.as_u64();
-            if let Ok(rr) = role.name.parse::<RatingRange>() {
+            if role_bindings.contains_key(&role_id) || team_roles.values().any(|&r| r == role_id) {
+                continue;
+            }
+
+            if let Ok(rule) = role.name.parse::<RoleRule>() {
                 info!(
                     "Adding new role {} (role_id={}) to guild {} (guild_id={})",
                     role.name, role_id, guild.name, guild_id
                 );
-                role_manager.add_rating_range(guild_id, role_id, rr);
+                role_manager.add_role(guild_id, role_id, rule);
+            }
+        }
+
+        for (team_id, role_id) in team_roles {
+            if !guild.roles.contains_key(&RoleId(role_id)) {
+                warn!(
+                    "Ignoring lichess team {} mapped to missing role_id={} in guild_id={}",
+                    team_id, role_id, guild_id
+                );
+                continue;
+            }
+
+            info!(
+                "Adding role_id={} for lichess team {} to guild_id={}",
+                role_id, team_id, guild_id
+            );
+            role_manager.add_role(guild_id, role_id, RoleRule::Team(team_id));
+        }
+
+        for (role_id, rule) in role_bindings {
+            if !guild.roles.contains_key(&RoleId(role_id)) {
+                warn!(
+                    "Ignoring rule {} bound to missing role_id={} in guild_id={}",
+                    rule, role_id, guild_id
+                );
+                continue;
+            }
+
+            match RoleRule::parse_binding(&rule) {
+                Ok(parsed) => {
+                    info!(
+                        "Adding role_id={} bound to {} to guild_id={}",
+                        role_id, rule, guild_id
+                    );
+                    role_manager.add_role(guild_id, role_id, parsed);
+                }
+                Err(e) => error!(
+                    "Unable to parse rule {} bound to role_id={} in guild_id={}: {}",
+                    rule, role_id, guild_id, e
+                ),
             }
         }
+
+        drop(data);
+        run_preflight(&ctx, &mut role_manager, guild_id).await;
     }
 
     async fn guild_delete(&self, ctx: Context, guild: GuildUnavailable) {
         trace!("Handler::guild_delete() called");
         let guild_id = *guild.id.as_u64();
+
+        // Guilds also go unavailable during Discord outages, their configuration is only purged
+        // once the bot was actually removed from them
+        if guild.unavailable {
+            warn!("Guild guild_id={} became unavailable", guild_id);
+            return;
+        }
+
         let data = ctx.data.read().await;
         let pool = data.get::<PoolContainer>().unwrap().clone();
 
@@ -86,11 +207,16 @@ impl EventHandler for Handler {
             "Adding role {} (role_id={}) to guild_id={}",
             role.name, role.id, guild_id
         );
-        let data = ctx.data.read().await;
-        let mut role_manager = data.get::<RoleManagerContainer>().unwrap().clone();
+        let mut role_manager;
+        {
+            let data = ctx.data.read().await;
+            role_manager = data.get::<RoleManagerContainer>().unwrap().clone();
+        }
 
-        if let Ok(rr) = role.name.parse::<RatingRange>() {
-            role_manager.add_rating_range(*guild_id.as_u64(), *role.id.as_u64(), rr);
+        if let Ok(rule) = role.name.parse::<RoleRule>() {
+            role_manager.add_role(*guild_id.as_u64(), *role.id.as_u64(), rule);
+            log_range_issues(&role_manager, *guild_id.as_u64());
+            run_preflight(&ctx, &mut role_manager, *guild_id.as_u64()).await;
         }
     }
 
@@ -99,27 +225,70 @@ impl EventHandler for Handler {
         let guild_id = *guild_id.as_u64();
         let role_id = *role.id.as_u64();
 
-        let data = ctx.data.read().await;
-        let mut role_manager = data.get::<RoleManagerContainer>().unwrap().clone();
+        let pool;
+        let mut role_manager;
+        {
+            let data = ctx.data.read().await;
+            pool = data.get::<PoolContainer>().unwrap().clone();
+            role_manager = data.get::<RoleManagerContainer>().unwrap().clone();
+        }
 
-        role_manager.remove_role(guild_id, role_id);
+        // Bound roles keep their rule whatever they are renamed to
+        let is_bound = match models::Guild::find(&pool, guild_id).await {
+            Ok(guild) => guild.map(|g| g.is_bound(role_id)).unwrap_or(false),
+            Err(e) => {
+                error!("Unable to look up guild_id={}: {}", guild_id, e);
+                return;
+            }
+        };
 
-        if let Ok(rr) = role.name.parse::<RatingRange>() {
-            info!(
-                "Updating role {} (role_id={}) in guild_id={}",
-                role.name, role_id, guild_id
-            );
-            role_manager.add_rating_range(guild_id, role_id, rr);
+        if !is_bound {
+            let was_managed = role_manager.remove_role(guild_id, role_id);
+
+            if let Ok(rule) = role.name.parse::<RoleRule>() {
+                info!(
+                    "Updating role {} (role_id={}) in guild_id={}",
+                    role.name, role_id, guild_id
+                );
+                role_manager.add_role(guild_id, role_id, rule);
+                log_range_issues(&role_manager, guild_id);
+            } else if was_managed {
+                log_range_issues(&role_manager, guild_id);
+            }
         }
+
+        // Moving any role, including the bot's own, may change which roles it can hand out
+        run_preflight(&ctx, &mut role_manager, guild_id).await;
     }
 
     async fn guild_role_delete(&self, ctx: Context, guild_id: GuildId, role_id: RoleId) {
         trace!("Handler::guild_role_delete() called");
         info!("Removing role_id={} from guild_id={}", role_id, guild_id);
-        let data = ctx.data.read().await;
-        let mut role_manager = data.get::<RoleManagerContainer>().unwrap().clone();
+        let guild_id = *guild_id.as_u64();
+        let role_id = *role_id.as_u64();
+        let pool;
+        let mut role_manager;
+        {
+            let data = ctx.data.read().await;
+            pool = data.get::<PoolContainer>().unwrap().clone();
+            role_manager = data.get::<RoleManagerContainer>().unwrap().clone();
+        }
+
+        let result = match models::Guild::find(&pool, guild_id).await {
+            Ok(Some(mut guild)) => guild.forget_role(&pool, role_id).await,
+            result => result.map(|_| ()),
+        };
+
+        if let Err(e) = result {
+            error!(
+                "Unable to remove bindings of role_id={} from guild_id={}: {}",
+                role_id, guild_id, e
+            );
+        }
 
-        role_manager.remove_role(*guild_id.as_u64(), *role_id.as_u64());
+        if role_manager.remove_role(guild_id, role_id) {
+            run_preflight(&ctx, &mut role_manager, guild_id).await;
+        }
     }
 
     // Set a handler to be called on the `ready` event. This is called when a
@@ -140,15 +309,301 @@ impl EventHandler for Handler {
                     )
                 })
                 .create_application_command(|command| {
-                    command.name("link").description(
-                        "Connects your lichess.org account with Liro. Needed to update ratings.",
-                    )
+                    command
+                        .name("link")
+                        .description(
+                            "Connects your lichess.org account with Liro. Needed to update \
+                            ratings.",
+                        )
+                        .create_option(|option| {
+                            option
+                                .name("chesscom")
+                                .description("Connect this chess.com account instead")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(false)
+                        })
                 })
                 .create_application_command(|command| {
-                    command.name("unlink").description(
-                        "Deletes all your information from the bot and removes your Discord roles.",
+                    command.name("verify").description(
+                        "Completes the connection of your chess.com account with Liro.",
                     )
                 })
+                .create_application_command(|command| {
+                    command
+                        .name("config")
+                        .description("Changes how Liro behaves on this server (admins only).")
+                        .create_option(|option| {
+                            option
+                                .name("formats")
+                                .description("Shows or changes the formats displayed by /rating")
+                                .kind(ApplicationCommandOptionType::SubCommand)
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("formats")
+                                        .description("Comma separated list, e.g. blitz, chess960")
+                                        .kind(ApplicationCommandOptionType::String)
+                                        .required(false)
+                                })
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("eligibility")
+                                .description("Shows or changes which ratings count for roles")
+                                .kind(ApplicationCommandOptionType::SubCommand)
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("provisional")
+                                        .description("Whether provisional ratings count")
+                                        .kind(ApplicationCommandOptionType::Boolean)
+                                        .required(false)
+                                })
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("format")
+                                        .description("Format the minimum number of games is for")
+                                        .kind(ApplicationCommandOptionType::String)
+                                        .required(false)
+                                })
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("min_games")
+                                        .description("Minimum number of rated games, 0 to remove")
+                                        .kind(ApplicationCommandOptionType::Integer)
+                                        .required(false)
+                                })
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("max_rd")
+                                        .description("Maximum rating deviation, 0 to remove")
+                                        .kind(ApplicationCommandOptionType::Integer)
+                                        .required(false)
+                                })
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("team")
+                                .description("Shows or changes the role given to a lichess team")
+                                .kind(ApplicationCommandOptionType::SubCommand)
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("team")
+                                        .description("ID or URL of the lichess team")
+                                        .kind(ApplicationCommandOptionType::String)
+                                        .required(false)
+                                })
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("role")
+                                        .description("Role to give, leave out to remove")
+                                        .kind(ApplicationCommandOptionType::Role)
+                                        .required(false)
+                                })
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("accounts")
+                                .description("Shows or changes which accounts roles are based on")
+                                .kind(ApplicationCommandOptionType::SubCommand)
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("roles")
+                                        .description("Accounts of members with several of them")
+                                        .kind(ApplicationCommandOptionType::String)
+                                        .add_string_choice("Primary account only", "primary")
+                                        .add_string_choice("Best rating of all accounts", "best")
+                                        .required(false)
+                                })
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("demotion")
+                                .description("Shows or changes how long members keep rating roles")
+                                .kind(ApplicationCommandOptionType::SubCommand)
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("grace")
+                                        .description("Points members may drop out of a range by")
+                                        .kind(ApplicationCommandOptionType::Integer)
+                                        .required(false)
+                                })
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("hours")
+                                        .description("Hours before members out of a range lose it")
+                                        .kind(ApplicationCommandOptionType::Integer)
+                                        .required(false)
+                                })
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("flagged")
+                                .description(
+                                    "Shows or changes what happens to members with closed or \
+                                    flagged accounts",
+                                )
+                                .kind(ApplicationCommandOptionType::SubCommand)
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("strip")
+                                        .description("Whether they lose their rating roles")
+                                        .kind(ApplicationCommandOptionType::Boolean)
+                                        .required(false)
+                                })
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("notify")
+                                        .description("Whether the admins are told about them")
+                                        .kind(ApplicationCommandOptionType::Boolean)
+                                        .required(false)
+                                })
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("alerts")
+                                .description(
+                                    "Shows or changes where warnings for admins are posted",
+                                )
+                                .kind(ApplicationCommandOptionType::SubCommand)
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("channel")
+                                        .description("Channel to post warnings in")
+                                        .kind(ApplicationCommandOptionType::Channel)
+                                        .required(false)
+                                })
+                        })
+                })
+                .create_application_command(|command| {
+                    command
+                        .name("roles")
+                        .description("Manages the roles given by Liro (admins only).")
+                        .create_option(|option| {
+                            option
+                                .name("bind")
+                                .description("Gives a role for a rule, whatever it is called")
+                                .kind(ApplicationCommandOptionType::SubCommand)
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("role")
+                                        .description("Role to give")
+                                        .kind(ApplicationCommandOptionType::Role)
+                                        .required(true)
+                                })
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("rule")
+                                        .description(
+                                            "Rule written like a role name, e.g. 1800+ blitz",
+                                        )
+                                        .kind(ApplicationCommandOptionType::String)
+                                        .required(true)
+                                })
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("unbind")
+                                .description("Removes the rule bound to a role")
+                                .kind(ApplicationCommandOptionType::SubCommand)
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("role")
+                                        .description("Role to unbind")
+                                        .kind(ApplicationCommandOptionType::Role)
+                                        .required(true)
+                                })
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("list")
+                                .description("Lists the roles managed by Liro")
+                                .kind(ApplicationCommandOptionType::SubCommand)
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("check")
+                                .description("Looks for overlapping, missing or unreachable ranges")
+                                .kind(ApplicationCommandOptionType::SubCommand)
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("generate")
+                                .description("Creates a ladder of rating roles")
+                                .kind(ApplicationCommandOptionType::SubCommand)
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("format")
+                                        .description("Format of the roles, e.g. blitz or best")
+                                        .kind(ApplicationCommandOptionType::String)
+                                        .required(true)
+                                })
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("step")
+                                        .description("Rating points covered by each role")
+                                        .kind(ApplicationCommandOptionType::Integer)
+                                        .required(true)
+                                })
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("from")
+                                        .description("Lowest rating of the ladder")
+                                        .kind(ApplicationCommandOptionType::Integer)
+                                        .required(true)
+                                })
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("to")
+                                        .description("Rating from which the top role is given")
+                                        .kind(ApplicationCommandOptionType::Integer)
+                                        .required(true)
+                                })
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("remove-generated")
+                                .description("Deletes every role created by /roles generate")
+                                .kind(ApplicationCommandOptionType::SubCommand)
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("preview")
+                                .description("Shows the roles a rating update would change")
+                                .kind(ApplicationCommandOptionType::SubCommand)
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("member")
+                                        .description("Member to preview, everyone by default")
+                                        .kind(ApplicationCommandOptionType::User)
+                                })
+                        })
+                })
+                .create_application_command(|command| {
+                    command
+                        .name("unlink")
+                        .description(
+                            "Deletes all your information from the bot and removes your Discord \
+                            roles.",
+                        )
+                        .create_option(|option| {
+                            option
+                                .name("account")
+                                .description("Only unlink this account, e.g. chesscom:username")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(false)
+                        })
+                })
+                .create_application_command(|command| {
+                    command
+                        .name("primary")
+                        .description("Picks the account your roles are based on.")
+                        .create_option(|option| {
+                            option
+                                .name("account")
+                                .description("Username of the account, e.g. chesscom:username")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true)
+                        })
+                })
         })
         .await;
 
@@ -177,32 +632,118 @@ impl EventHandler for Handler {
                 "Handling application command '/{}' for discord_id={} in guild_id={}",
                 command.data.name, discord_id, guild_id
             );
+
+            let deferred = is_slow(&command);
+            let private = defers_privately(&command);
+            if deferred {
+                if let Err(why) = command
+                    .create_interaction_response(&ctx.http, |response| {
+                        response
+                            .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                            .interaction_response_data(|message| {
+                                if private {
+                                    message.flags(
+                                        InteractionApplicationCommandCallbackDataFlags::EPHEMERAL,
+                                    );
+                                }
+                                message
+                            })
+                    })
+                    .await
+                {
+                    error!("Cannot defer slash command: {}", why);
+                    return;
+                }
+            }
+
             let command_response = match command.data.name.as_str() {
                 "rating" => update_ratings(&ctx, guild_id, discord_id).await,
-                "link" => link(&ctx, guild_id, discord_id).await,
-                "unlink" => unlink(&ctx, guild_id, discord_id).await,
+                "link" => {
+                    let chesscom_username = get_string_option(&command.data.options, "chesscom");
+                    link(&ctx, guild_id, discord_id, chesscom_username).await
+                }
+                "verify" => verify(&ctx, guild_id, discord_id).await,
+                "config" if !is_admin(command.member.as_ref()) => {
+                    Ok(CommandResponse::PrivateSentence(
+                        "Only server admins can change my configuration.".to_string(),
+                    ))
+                }
+                "config" => config(&ctx, guild_id, &command.data.options).await,
+                "roles" if !is_admin(command.member.as_ref()) => {
+                    Ok(CommandResponse::PrivateSentence(
+                        "Only server admins can manage my roles.".to_string(),
+                    ))
+                }
+                "roles" => roles(&ctx, guild_id, &command.data.options).await,
+                "unlink" => {
+                    let account = get_string_option(&command.data.options, "account");
+                    unlink(&ctx, guild_id, discord_id, account).await
+                }
+                "primary" => {
+                    let account = get_string_option(&command.data.options, "account");
+                    primary(&ctx, guild_id, discord_id, account.unwrap_or_default()).await
+                }
                 _ => unreachable!(),
             };
 
-            if let Err(why) = command
-                .create_interaction_response(&ctx.http, |response| {
-                    response.interaction_response_data(|message| match command_response {
-                        Ok(CommandResponse::Embed(e)) => message.add_embed(e),
-                        Ok(CommandResponse::PrivateEmbed(e)) => message
-                            .add_embed(e)
-                            .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL),
-                        Ok(CommandResponse::Sentence(s)) => message.content(s),
-                        Ok(CommandResponse::PrivateSentence(s)) => message
-                            .content(s)
-                            .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL),
-                        Err(why) => {
-                            error!("Error handling command: {}", why);
-                            message.content("Internal bot error. @teotwaki, I'm scared.")
-                        }
+            let whispered = matches!(
+                command_response,
+                Ok(CommandResponse::PrivateEmbed(_)) | Ok(CommandResponse::PrivateSentence(_))
+            );
+            let result = if deferred && !private && whispered {
+                // A public "thinking" message can't be made private, so a private follow-up
+                // takes its place
+                if let Err(why) = command
+                    .delete_original_interaction_response(&ctx.http)
+                    .await
+                {
+                    warn!("Cannot delete deferred slash command answer: {}", why);
+                }
+                command
+                    .create_followup_message(&ctx.http, |message| {
+                        match command_response {
+                            Ok(CommandResponse::PrivateEmbed(e)) => message.add_embed(e),
+                            Ok(CommandResponse::PrivateSentence(s)) => message.content(s),
+                            _ => unreachable!(),
+                        };
+                        message.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
                     })
-                })
-                .await
-            {
+                    .await
+                    .map(|_| ())
+            } else if deferred {
+                // The visibility of the answer was set when deferring it
+                command
+                    .edit_original_interaction_response(
+                        &ctx.http,
+                        |response| match command_response {
+                            Ok(CommandResponse::Embed(e))
+                            | Ok(CommandResponse::PrivateEmbed(e)) => response.add_embed(e),
+                            Ok(CommandResponse::Sentence(s))
+                            | Ok(CommandResponse::PrivateSentence(s)) => response.content(s),
+                            Err(why) => response.content(error_message(&why)),
+                        },
+                    )
+                    .await
+                    .map(|_| ())
+            } else {
+                command
+                    .create_interaction_response(&ctx.http, |response| {
+                        response.interaction_response_data(|message| match command_response {
+                            Ok(CommandResponse::Embed(e)) => message.add_embed(e),
+                            Ok(CommandResponse::PrivateEmbed(e)) => message
+                                .add_embed(e)
+                                .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL),
+                            Ok(CommandResponse::Sentence(s)) => message.content(s),
+                            Ok(CommandResponse::PrivateSentence(s)) => message
+                                .content(s)
+                                .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL),
+                            Err(why) => message.content(error_message(&why)),
+                        })
+                    })
+                    .await
+            };
+
+            if let Err(why) = result {
                 error!("Cannot respond to slash command: {}", why);
             }
         }
@@ -0,0 +1,128 @@
+use crate::{
+    lichess::Format,
+    models::{Account, RoleSource, User},
+    ratings::{Provider, Rating, RatingPolicy, Ratings, Title},
+};
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// What is known about a member when deciding which roles they should have
+#[derive(Debug, Clone, Default)]
+pub struct MemberProfile {
+    pub ratings: Ratings,
+    pub peaks: Ratings,
+    pub title: Option<Title>,
+    pub patron: bool,
+    pub verified: bool,
+    pub teams: Vec<String>,
+    /// Days since the oldest account was created, when known
+    pub account_age: Option<u64>,
+}
+
+impl MemberProfile {
+    /// Combines the accounts of `user` picked by `source`
+    ///
+    /// When several accounts have a rating in the same format on the same provider, the highest
+    /// rating accepted by `policy` is kept.
+    pub fn new(user: &User, source: RoleSource, policy: &RatingPolicy) -> Self {
+        trace!("MemberProfile::new() called");
+        let accounts: Vec<&Account> = match source {
+            RoleSource::Primary => user.get_primary_account().into_iter().collect(),
+            RoleSource::Best => user.get_accounts().iter().collect(),
+        };
+        let created_at = accounts.iter().filter_map(|a| a.get_created_at()).min();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let mut profile = MemberProfile::from_accounts(accounts, policy);
+        profile.account_age = created_at.map(|t| now.saturating_sub(t) / 86400);
+        profile
+    }
+
+    fn from_accounts<'a, A>(accounts: A, policy: &RatingPolicy) -> Self
+    where
+        A: IntoIterator<Item = &'a Account>,
+    {
+        trace!("MemberProfile::from_accounts() called");
+        let mut ratings: HashMap<(Provider, Format), Rating> = HashMap::new();
+        let mut peaks: HashMap<(Provider, Format), i16> = HashMap::new();
+        let mut profile = MemberProfile::default();
+
+        for account in accounts {
+            let provider = account.provider();
+
+            for (&format, rating) in account.get_ratings() {
+                let rank = |r: &Rating| (policy.check(format, r).is_ok(), r.rating());
+                let best = ratings.entry((provider, format)).or_insert(*rating);
+                if rank(rating) > rank(best) {
+                    *best = *rating;
+                }
+            }
+
+            for (&format, &peak) in account.get_peaks() {
+                let best = peaks.entry((provider, format)).or_insert(peak);
+                *best = peak.max(*best);
+            }
+
+            profile.title = profile.title.or_else(|| account.get_title());
+            profile.patron |= account.is_patron();
+            profile.verified |= account.is_verified();
+            profile.teams.extend_from_slice(account.get_teams());
+        }
+
+        profile.ratings = ratings.into_iter().map(|((p, f), r)| (p, f, r)).collect();
+        profile.peaks = peaks.into_iter().map(|((p, f), r)| (p, f, r)).collect();
+        profile
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(json: &str) -> Account {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn best_rating_across_accounts_is_kept() {
+        let accounts = [
+            account(r#"{"provider":"lichess","username":"a","ratings":{"blitz":1500}}"#),
+            account(r#"{"provider":"lichess","username":"b","ratings":{"blitz":1700}}"#),
+            account(r#"{"provider":"chesscom","username":"c","ratings":{"blitz":1200}}"#),
+        ];
+
+        let profile = MemberProfile::from_accounts(&accounts, &RatingPolicy::default());
+
+        assert_eq!(
+            profile.ratings.get(Provider::Lichess, Format::Blitz),
+            Some(&Rating::from(1700))
+        );
+        assert_eq!(
+            profile.ratings.get(Provider::Chesscom, Format::Blitz),
+            Some(&Rating::from(1200))
+        );
+    }
+
+    #[test]
+    fn ratings_refused_by_the_policy_are_not_preferred() {
+        let accounts = [
+            account(r#"{"provider":"lichess","username":"a","ratings":{"blitz":1500}}"#),
+            account(
+                r#"{"provider":"lichess","username":"b","ratings":{"blitz":{"rating":1900,
+                "rd":null,"progress":0,"games":3,"provisional":true}}}"#,
+            ),
+        ];
+
+        let profile = MemberProfile::from_accounts(&accounts, &RatingPolicy::default());
+
+        assert_eq!(
+            profile.ratings.get(Provider::Lichess, Format::Blitz),
+            Some(&Rating::from(1500))
+        );
+    }
+}
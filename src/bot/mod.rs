@@ -1,8 +1,16 @@
+mod account_role;
 mod commands;
 mod handler;
+mod member_profile;
+mod preflight;
+mod range_check;
 mod rating_range;
+mod role_ladder;
 mod role_manager;
+mod role_rule;
+mod rule_expr;
 mod run;
+mod sync;
 
 pub use handler::Handler;
 pub use run::run;
@@ -0,0 +1,149 @@
+use std::fmt;
+
+/// Role managed by the bot, as seen by the preflight checks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManagedRole {
+    pub id: u64,
+    pub name: String,
+    pub position: i64,
+    /// Whether the role belongs to an integration, such as another bot or a server boost
+    pub integration: bool,
+}
+
+/// Problem preventing the bot from handing out the roles it manages in a guild
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PreflightIssue {
+    /// The bot can't give or remove any role
+    MissingManageRoles,
+    /// The role isn't below the highest role of the bot
+    AboveBot { role: u64, name: String },
+    /// The role is handed out by an integration
+    Integration { role: u64, name: String },
+}
+
+impl PreflightIssue {
+    /// Role affected by the issue, `None` if every role is
+    pub fn role_id(&self) -> Option<u64> {
+        match self {
+            PreflightIssue::MissingManageRoles => None,
+            PreflightIssue::AboveBot { role, .. } | PreflightIssue::Integration { role, .. } => {
+                Some(*role)
+            }
+        }
+    }
+}
+
+impl fmt::Display for PreflightIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PreflightIssue::MissingManageRoles => write!(
+                f,
+                "I don't have the Manage Roles permission, so I can't give or remove any role. \
+                Please grant it to the `liro` role."
+            ),
+            PreflightIssue::AboveBot { name, .. } => write!(
+                f,
+                "`{}` isn't below my highest role, so I can't give or remove it. Please move it \
+                under the `liro` role in the server settings.",
+                name
+            ),
+            PreflightIssue::Integration { name, .. } => write!(
+                f,
+                "`{}` is managed by an integration, so I can't give or remove it. Please bind \
+                another role instead.",
+                name
+            ),
+        }
+    }
+}
+
+/// Checks whether the bot can hand out `roles`
+///
+/// `manage_roles` tells whether any role of the bot grants the Manage Roles permission, and
+/// `bot_position` is the position of its highest role.
+pub fn check_permissions(
+    manage_roles: bool,
+    bot_position: Option<i64>,
+    roles: &[ManagedRole],
+) -> Vec<PreflightIssue> {
+    trace!("check_permissions() called");
+    if !manage_roles {
+        return vec![PreflightIssue::MissingManageRoles];
+    }
+
+    // Bots can only hand out the roles strictly below their highest role
+    let is_below_bot = |role: &ManagedRole| match bot_position {
+        Some(position) => role.position < position,
+        None => false,
+    };
+
+    let mut issues: Vec<PreflightIssue> = roles
+        .iter()
+        .filter_map(|role| {
+            if role.integration {
+                Some(PreflightIssue::Integration {
+                    role: role.id,
+                    name: role.name.clone(),
+                })
+            } else if !is_below_bot(role) {
+                Some(PreflightIssue::AboveBot {
+                    role: role.id,
+                    name: role.name.clone(),
+                })
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    issues.sort_by_key(|issue| issue.role_id());
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn role(id: u64, position: i64) -> ManagedRole {
+        ManagedRole {
+            id,
+            name: format!("role {}", id),
+            position,
+            integration: false,
+        }
+    }
+
+    #[test]
+    fn check_permissions_requires_manage_roles() {
+        assert_eq!(
+            check_permissions(false, Some(10), &[role(1, 2)]),
+            vec![PreflightIssue::MissingManageRoles]
+        );
+    }
+
+    #[test]
+    fn check_permissions_reports_roles_not_below_the_bot() {
+        let roles = [role(1, 2), role(2, 5), role(3, 6)];
+        let issues = check_permissions(true, Some(5), &roles);
+
+        assert_eq!(
+            issues.iter().map(|i| i.role_id()).collect::<Vec<_>>(),
+            vec![Some(2), Some(3)]
+        );
+        assert_eq!(check_permissions(true, None, &roles).len(), 3);
+    }
+
+    #[test]
+    fn check_permissions_reports_integration_roles() {
+        let roles = [ManagedRole {
+            integration: true,
+            ..role(1, 2)
+        }];
+
+        assert!(matches!(
+            check_permissions(true, Some(5), &roles)[..],
+            [PreflightIssue::Integration { role: 1, .. }]
+        ));
+        assert!(check_permissions(true, Some(5), &[role(1, 2)]).is_empty());
+    }
+}
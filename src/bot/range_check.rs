@@ -0,0 +1,269 @@
+use super::rating_range::{RatingRange, Target};
+use crate::ratings::Provider;
+use std::fmt;
+use strum::IntoEnumIterator;
+
+/// Problem found in the rating ranges of a guild
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeIssue {
+    /// Both roles are given to members rated between `from` and `to`
+    Overlap {
+        roles: (u64, u64),
+        names: (String, String),
+        from: i16,
+        to: i16,
+    },
+    /// No role is given to members rated between `from` and `to`, although roles exist on both
+    /// sides
+    Gap {
+        roles: (u64, u64),
+        names: (String, String),
+        from: i16,
+        to: i16,
+    },
+    /// The role can never be given
+    Unreachable {
+        role: u64,
+        name: String,
+        reason: String,
+    },
+}
+
+fn describe_bound(rating: i16) -> String {
+    if rating == i16::MAX {
+        "any rating".to_string()
+    } else {
+        rating.to_string()
+    }
+}
+
+impl fmt::Display for RangeIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RangeIssue::Overlap {
+                names, from, to, ..
+            } => write!(
+                f,
+                "`{}` and `{}` overlap from {} to {}",
+                names.0,
+                names.1,
+                from,
+                describe_bound(*to)
+            ),
+            RangeIssue::Gap {
+                names, from, to, ..
+            } => write!(
+                f,
+                "nothing is given from {} to {}, between `{}` and `{}`",
+                from, to, names.0, names.1
+            ),
+            RangeIssue::Unreachable { name, reason, .. } => {
+                write!(f, "`{}` can never be given: {}", name, reason)
+            }
+        }
+    }
+}
+
+fn name(range: &RatingRange) -> String {
+    range.get_name().unwrap_or_else(|| range.to_string())
+}
+
+fn unreachable_reason(range: &RatingRange) -> Option<String> {
+    if range.lowest() > range.highest() {
+        return Some("its range is empty".to_string());
+    }
+
+    let providers: Vec<Provider> = match range.provider() {
+        Some(provider) => vec![provider],
+        None => Provider::iter().collect(),
+    };
+
+    if let Target::Format(format) = range.target() {
+        if !providers.iter().any(|p| p.formats().contains(&format)) {
+            return Some(format!("{} has no {} rating", providers[0].name(), format));
+        }
+    }
+
+    let floor = providers.iter().map(|p| p.rating_floor()).min()?;
+    if range.highest() < floor {
+        return Some(format!("ratings never go below {}", floor));
+    }
+
+    None
+}
+
+/// Looks for overlapping, missing and unreachable ranges among `ranges`
+///
+/// Ranges are compared within the same format or aggregate, and peak ranges are only compared
+/// with other peak ranges. Ranges without a provider are compared with the ranges of every
+/// provider.
+pub fn check_ranges(ranges: &[(u64, RatingRange)]) -> Vec<RangeIssue> {
+    trace!("check_ranges() called");
+    let mut issues = vec![];
+
+    let mut reachable: Vec<&(u64, RatingRange)> = vec![];
+    for entry in ranges {
+        match unreachable_reason(&entry.1) {
+            Some(reason) => issues.push(RangeIssue::Unreachable {
+                role: entry.0,
+                name: name(&entry.1),
+                reason,
+            }),
+            None => reachable.push(entry),
+        }
+    }
+
+    reachable.sort_by_key(|(role_id, range)| (range.lowest(), range.highest(), *role_id));
+
+    for (i, (first_id, first)) in reachable.iter().enumerate() {
+        for (second_id, second) in reachable.iter().skip(i + 1) {
+            let comparable = first.target() == second.target()
+                && first.is_peak() == second.is_peak()
+                && match (first.provider(), second.provider()) {
+                    (Some(a), Some(b)) => a == b,
+                    _ => true,
+                };
+
+            if !comparable {
+                continue;
+            }
+
+            let from = first.lowest().max(second.lowest());
+            let to = first.highest().min(second.highest());
+            if from <= to {
+                issues.push(RangeIssue::Overlap {
+                    roles: (*first_id, *second_id),
+                    names: (name(first), name(second)),
+                    from,
+                    to,
+                });
+            }
+        }
+    }
+
+    // Gaps are only looked for between ranges of the same provider, as a ladder is usually
+    // built for a single provider or for all of them
+    let mut ladders: Vec<Vec<&(u64, RatingRange)>> = vec![];
+    for entry in reachable {
+        let range = &entry.1;
+        match ladders.iter_mut().find(|l| {
+            l[0].1.target() == range.target()
+                && l[0].1.is_peak() == range.is_peak()
+                && l[0].1.provider() == range.provider()
+        }) {
+            Some(ladder) => ladder.push(entry),
+            None => ladders.push(vec![entry]),
+        }
+    }
+
+    for ladder in ladders {
+        let mut covered = ladder[0];
+        for entry in ladder.iter().skip(1) {
+            if covered.1.highest() == i16::MAX {
+                break;
+            }
+
+            if entry.1.lowest() > covered.1.highest() + 1 {
+                issues.push(RangeIssue::Gap {
+                    roles: (covered.0, entry.0),
+                    names: (name(&covered.1), name(&entry.1)),
+                    from: covered.1.highest() + 1,
+                    to: entry.1.lowest() - 1,
+                });
+            }
+
+            if entry.1.highest() > covered.1.highest() {
+                covered = entry;
+            }
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lichess::Format;
+
+    #[test]
+    fn contiguous_ladders_have_no_issues() {
+        let ranges = [
+            (1, "U1200 blitz".parse().unwrap()),
+            (2, "1200-1499 blitz".parse().unwrap()),
+            (3, "1500-1999 blitz".parse().unwrap()),
+            (4, "2000+ blitz".parse().unwrap()),
+            (5, "1200+ rapid".parse().unwrap()),
+        ];
+
+        assert_eq!(check_ranges(&ranges), vec![]);
+    }
+
+    #[test]
+    fn overlaps_are_reported() {
+        let ranges = [
+            (1, "1200-1599 blitz".parse().unwrap()),
+            (2, "1500+ blitz lichess".parse().unwrap()),
+            (3, "1500+ blitz chesscom".parse().unwrap()),
+        ];
+
+        let issues = check_ranges(&ranges);
+        assert_eq!(issues.len(), 2);
+        assert!(issues.contains(&RangeIssue::Overlap {
+            roles: (1, 2),
+            names: (
+                "1200-1599 blitz".to_string(),
+                "1500+ blitz lichess".to_string()
+            ),
+            from: 1500,
+            to: 1599,
+        }));
+        assert!(matches!(
+            issues[1],
+            RangeIssue::Overlap { roles: (1, 3), .. }
+        ));
+    }
+
+    #[test]
+    fn gaps_are_reported() {
+        let ranges = [
+            (1, "1200-1399 blitz".parse().unwrap()),
+            (2, "1500+ blitz".parse().unwrap()),
+        ];
+
+        assert_eq!(
+            check_ranges(&ranges),
+            vec![RangeIssue::Gap {
+                roles: (1, 2),
+                names: ("1200-1399 blitz".to_string(), "1500+ blitz".to_string()),
+                from: 1400,
+                to: 1499,
+            }]
+        );
+    }
+
+    #[test]
+    fn peak_ranges_are_checked_separately() {
+        let ranges = [
+            (1, "1500+ blitz".parse().unwrap()),
+            (2, "peak 1500+ blitz".parse().unwrap()),
+        ];
+
+        assert_eq!(check_ranges(&ranges), vec![]);
+    }
+
+    #[test]
+    fn unreachable_ranges_are_reported() {
+        let ranges = [
+            (1, "1500+ chess960 chesscom".parse().unwrap()),
+            (2, "U300 blitz lichess".parse().unwrap()),
+            (3, RatingRange::new(Format::Blitz, Some(1500), Some(1400))),
+        ];
+
+        let issues = check_ranges(&ranges);
+        assert_eq!(issues.len(), 3);
+        assert!(issues
+            .iter()
+            .all(|i| matches!(i, RangeIssue::Unreachable { .. })));
+    }
+}
@@ -1,42 +1,134 @@
-use crate::lichess::Format;
+use crate::{lichess::Format, ratings::Provider};
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::{fmt, str::FromStr};
 
+/// Ratings a range is compared against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// The rating in a single format
+    Format(Format),
+    /// The highest rating across the formats enabled in the guild
+    Best,
+    /// The average rating across the formats enabled in the guild
+    Average,
+    /// Every format enabled in the guild, each rating must be in the range
+    All,
+}
+
+impl Target {
+    pub fn key(&self) -> &'static str {
+        match self {
+            Target::Format(format) => format.key(),
+            Target::Best => "best",
+            Target::Average => "average",
+            Target::All => "all",
+        }
+    }
+}
+
+impl From<Format> for Target {
+    fn from(format: Format) -> Self {
+        Target::Format(format)
+    }
+}
+
+impl FromStr for Target {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "best" => Ok(Target::Best),
+            "average" | "avg" => Ok(Target::Average),
+            "all" => Ok(Target::All),
+            _ => Ok(Target::Format(s.parse()?)),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct RatingRange {
-    format: Format,
+    target: Target,
     min: Option<i16>,
     max: Option<i16>,
+    provider: Option<Provider>,
+    peak: bool,
 }
 
 impl RatingRange {
-    pub fn new<F, O>(format: F, min: O, max: O) -> RatingRange
+    pub fn new<T, O>(target: T, min: O, max: O) -> RatingRange
     where
-        F: Into<Format>,
+        T: Into<Target>,
         O: Into<Option<i16>>,
     {
         trace!("RatingRange::new() called");
         let rr = RatingRange {
-            format: format.into(),
+            target: target.into(),
             min: min.into(),
             max: max.into(),
+            provider: None,
+            peak: false,
         };
         debug!("Creating new {}", rr);
         rr
     }
 
-    pub fn is_match<F>(&self, format: F, rating: i16) -> bool
-    where
-        F: Into<Format>,
-    {
-        trace!("RatingRange::is_match() called");
-        let format = format.into();
+    /// Restricts the range to ratings coming from `provider`
+    ///
+    /// Ranges without a provider match ratings from any provider.
+    pub fn with_provider(mut self, provider: Provider) -> RatingRange {
+        trace!("RatingRange::with_provider() called");
+        self.provider = Some(provider);
+        self
+    }
 
-        if self.format != format {
-            return false;
+    /// Makes the range apply to the highest rating ever reached instead of the current one
+    pub fn into_peak(mut self) -> RatingRange {
+        trace!("RatingRange::into_peak() called");
+        self.peak = true;
+        self
+    }
+
+    /// Widens the range by `grace` points on both of its bounds
+    pub fn widen(mut self, grace: i16) -> RatingRange {
+        trace!("RatingRange::widen() called");
+        self.min = self.min.map(|min| min.saturating_sub(grace));
+        self.max = self.max.map(|max| max.saturating_add(grace));
+        self
+    }
+
+    pub fn is_peak(&self) -> bool {
+        trace!("RatingRange::is_peak() called");
+        self.peak
+    }
+
+    pub fn target(&self) -> Target {
+        trace!("RatingRange::target() called");
+        self.target
+    }
+
+    pub fn provider(&self) -> Option<Provider> {
+        trace!("RatingRange::provider() called");
+        self.provider
+    }
+
+    /// Lowest rating matched by the range
+    pub fn lowest(&self) -> i16 {
+        trace!("RatingRange::lowest() called");
+        self.min.unwrap_or(i16::MIN)
+    }
+
+    /// Highest rating matched by the range, following the rules of [`RatingRange::is_match`]
+    pub fn highest(&self) -> i16 {
+        trace!("RatingRange::highest() called");
+        match (self.min, self.max) {
+            (Some(_), Some(max)) => max,
+            (None, Some(max)) => max.saturating_sub(1),
+            _ => i16::MAX,
         }
+    }
 
+    fn contains(&self, rating: i16) -> bool {
         match (self.min, self.max) {
             (Some(min), Some(max)) => rating >= min && rating <= max,
             (Some(min), None) => rating >= min,
@@ -45,25 +137,84 @@ impl RatingRange {
         }
     }
 
+    pub fn is_match<F>(&self, provider: Provider, format: F, rating: i16) -> bool
+    where
+        F: Into<Format>,
+    {
+        trace!("RatingRange::is_match() called");
+        if self.target != Target::Format(format.into()) {
+            return false;
+        }
+
+        if matches!(self.provider, Some(p) if p != provider) {
+            return false;
+        }
+
+        self.contains(rating)
+    }
+
+    /// Checks whether the member with `ratings` belongs to the range
+    ///
+    /// Aggregate ranges only consider the ratings in `formats`, the formats enabled in the guild.
+    pub fn matches_ratings<I>(&self, ratings: I, formats: &[Format]) -> bool
+    where
+        I: IntoIterator<Item = (Provider, Format, i16)>,
+    {
+        trace!("RatingRange::matches_ratings() called");
+        let ratings: Vec<(Provider, Format, i16)> = ratings
+            .into_iter()
+            .filter(|(p, _, _)| !matches!(self.provider, Some(provider) if provider != *p))
+            .collect();
+        let enabled = || {
+            ratings
+                .iter()
+                .filter(|(_, f, _)| formats.contains(f))
+                .map(|(_, _, r)| *r)
+        };
+
+        match self.target {
+            Target::Format(_) => ratings.iter().any(|&(p, f, r)| self.is_match(p, f, r)),
+            Target::Best => matches!(enabled().max(), Some(best) if self.contains(best)),
+            Target::Average => {
+                let (sum, count) = enabled().fold((0i32, 0i32), |(s, c), r| (s + r as i32, c + 1));
+                count > 0 && self.contains((sum / count) as i16)
+            }
+            Target::All => {
+                let offered: Vec<&Format> = formats
+                    .iter()
+                    .filter(|f| match self.provider {
+                        Some(provider) => provider.formats().contains(f),
+                        None => true,
+                    })
+                    .collect();
+
+                !offered.is_empty()
+                    && offered.iter().all(|&&format| {
+                        ratings
+                            .iter()
+                            .any(|&(_, f, r)| f == format && self.contains(r))
+                    })
+            }
+        }
+    }
+
     pub fn get_name(&self) -> Option<String> {
-        match (self.min, self.max) {
-            (Some(min), Some(max)) => Some(format!(
-                "{}-{} {}",
-                min,
-                max,
-                self.format.to_string().to_lowercase()
-            )),
-            (Some(min), None) => Some(format!(
-                "{}+ {}",
-                min,
-                self.format.to_string().to_lowercase()
-            )),
-            (None, Some(max)) => Some(format!(
-                "U{} {}",
-                max,
-                self.format.to_string().to_lowercase()
-            )),
-            _ => None,
+        let range = match (self.min, self.max) {
+            (Some(min), Some(max)) => format!("{}-{}", min, max),
+            (Some(min), None) => format!("{}+", min),
+            (None, Some(max)) => format!("U{}", max),
+            _ => return None,
+        };
+
+        let mut name = format!("{} {}", range, self.target.key());
+
+        if self.peak {
+            name = format!("peak {}", name);
+        }
+
+        match self.provider {
+            Some(provider) => Some(format!("{} {}", name, provider)),
+            None => Some(name),
         }
     }
 }
@@ -71,36 +222,111 @@ impl RatingRange {
 impl fmt::Display for RatingRange {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         trace!("RatingRange::fmt() called");
-        write!(f, "RatingRange<min={:?} max={:?}>", self.min, self.max)
+        write!(
+            f,
+            "RatingRange<min={:?} max={:?} provider={:?} peak={}>",
+            self.min, self.max, self.provider, self.peak
+        )
     }
 }
 
+/// Lowercases `s`, turns dashes into hyphens and replaces emoji and decorations with spaces
+fn normalize(s: &str) -> String {
+    let replaced: String = s
+        .chars()
+        .map(|c| match c {
+            '–' | '—' => '-',
+            c if c.is_ascii_alphanumeric() || "+-<>=.".contains(c) => c.to_ascii_lowercase(),
+            _ => ' ',
+        })
+        .collect();
+
+    replaced
+        .split_whitespace()
+        .map(|w| w.trim_matches('.'))
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Parses role names such as `1800+ blitz`, `Blitz 1800–1999`, `<1200 rapid chesscom` or
+/// `⚡ peak >=2000 bullet ⚡`
+///
+/// Lower bounds are always inclusive. Upper bounds written with `U`, `under` or `<` are
+/// exclusive, and `<=` is inclusive. The upper bound of a range such as `1800-1999` is always
+/// inclusive, as it was before this grammar, so that existing roles keep matching the same
+/// ratings.
 impl FromStr for RatingRange {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         lazy_static! {
-            static ref RE: Regex = Regex::new(r"^(((U|u)(?P<under>\d{3,4}))|((?P<min>\d{3,4})(\+|-(?P<max>\d{3,4})))) (?P<format>\w+)$").unwrap();
+            static ref RE: Regex = Regex::new(
+                r"(?x)
+                ^(?P<peak>peak\ )?
+                (?:(?P<before>[a-z0-9]+)\ )?
+                (?:
+                    (?:u|under\ |<\ ?)(?P<under>\d{3,5})
+                    | <=\ ?(?P<le>\d{3,5})
+                    | (?P<min>\d{3,5})\ ?\+
+                    | >=\ ?(?P<ge>\d{3,5})
+                    | >\ ?(?P<gt>\d{3,5})
+                    | (?P<from>\d{3,5})\ ?-\ ?(?P<to>\d{3,5})
+                )
+                (?:\ (?P<after>[a-z0-9]+))?
+                (?:\ (?P<provider>[a-z.]+))?$"
+            )
+            .unwrap();
         }
 
-        let captures = RE.captures(s).ok_or(())?;
-        let format = captures
-            .name("format")
-            .map(|m| m.as_str().parse::<Format>())
-            .ok_or(())??;
+        let normalized = normalize(s);
+        let captures = RE.captures(&normalized).ok_or(())?;
+        let number = |name: &str| -> Result<Option<i16>, ()> {
+            captures
+                .name(name)
+                .map(|m| m.as_str().parse::<i16>().map_err(|_| ()))
+                .transpose()
+        };
+
+        let (min, max) = if let Some(under) = number("under")? {
+            (None, Some(under))
+        } else if let Some(le) = number("le")? {
+            (None, Some(le.checked_add(1).ok_or(())?))
+        } else if let Some(min) = number("min")?.or(number("ge")?) {
+            (Some(min), None)
+        } else if let Some(gt) = number("gt")? {
+            (Some(gt.checked_add(1).ok_or(())?), None)
+        } else {
+            let from = number("from")?.ok_or(())?;
+            let to = number("to")?.ok_or(())?;
+            if to <= from {
+                return Err(());
+            }
 
-        let (min, max) = match (
-            captures.name("min"),
-            captures.name("max"),
-            captures.name("under"),
+            (Some(from), Some(to))
+        };
+
+        // The format comes either before or after the range, and the provider always comes last
+        let (format, provider) = match (
+            captures.name("before"),
+            captures.name("after"),
+            captures.name("provider"),
         ) {
-            (Some(min), None, _) => (min.as_str().parse().ok(), None),
-            (Some(min), Some(max), _) => (min.as_str().parse().ok(), max.as_str().parse().ok()),
-            (_, _, Some(max)) => (None, max.as_str().parse().ok()),
-            _ => unreachable!(),
+            (Some(format), provider, None) => (format, provider),
+            (None, Some(format), provider) => (format, provider),
+            _ => return Err(()),
         };
 
-        Ok(RatingRange::new(format, min, max))
+        let mut rr = RatingRange::new(format.as_str().parse::<Target>()?, min, max);
+
+        if captures.name("peak").is_some() {
+            rr = rr.into_peak();
+        }
+
+        match provider {
+            Some(provider) => Ok(rr.with_provider(provider.as_str().parse()?)),
+            None => Ok(rr),
+        }
     }
 }
 
@@ -114,9 +340,9 @@ mod tests {
         let f = Format::Blitz;
         let rr = RatingRange::new(f, None, Some(10));
 
-        assert!(rr.is_match(f, 9));
-        assert!(!rr.is_match(f, 10));
-        assert!(!rr.is_match(f, 11));
+        assert!(rr.is_match(Provider::Lichess, f, 9));
+        assert!(!rr.is_match(Provider::Lichess, f, 10));
+        assert!(!rr.is_match(Provider::Lichess, f, 11));
     }
 
     #[test]
@@ -125,9 +351,9 @@ mod tests {
         let f = Format::Blitz;
         let rr = RatingRange::new(f, Some(10), None);
 
-        assert!(!rr.is_match(f, 9));
-        assert!(rr.is_match(f, 10));
-        assert!(rr.is_match(f, 11));
+        assert!(!rr.is_match(Provider::Lichess, f, 9));
+        assert!(rr.is_match(Provider::Lichess, f, 10));
+        assert!(rr.is_match(Provider::Lichess, f, 11));
     }
 
     #[test]
@@ -136,36 +362,36 @@ mod tests {
         let f = Format::Blitz;
         let rr = RatingRange::new(f, Some(10), Some(19));
 
-        assert!(!rr.is_match(f, 9));
-        assert!(rr.is_match(f, 10));
-        assert!(rr.is_match(f, 19));
-        assert!(!rr.is_match(f, 20));
+        assert!(!rr.is_match(Provider::Lichess, f, 9));
+        assert!(rr.is_match(Provider::Lichess, f, 10));
+        assert!(rr.is_match(Provider::Lichess, f, 19));
+        assert!(!rr.is_match(Provider::Lichess, f, 20));
     }
 
     #[test]
     fn is_match_differentiates_on_format() {
         let rr = RatingRange::new(Format::Blitz, Some(10), Some(19));
 
-        assert!(!rr.is_match(Format::Bullet, 15));
+        assert!(!rr.is_match(Provider::Lichess, Format::Bullet, 15));
     }
 
     #[test]
     fn parse_correctly_detects_format() {
         assert_eq!(
-            "2200+ classical".parse::<RatingRange>().unwrap().format,
-            Format::Classical
+            "2200+ classical".parse::<RatingRange>().unwrap().target,
+            Target::Format(Format::Classical)
         );
         assert_eq!(
-            "2200+ blitz".parse::<RatingRange>().unwrap().format,
-            Format::Blitz
+            "2200+ blitz".parse::<RatingRange>().unwrap().target,
+            Target::Format(Format::Blitz)
         );
         assert_eq!(
-            "2200+ bullet".parse::<RatingRange>().unwrap().format,
-            Format::Bullet
+            "2200+ bullet".parse::<RatingRange>().unwrap().target,
+            Target::Format(Format::Bullet)
         );
         assert_eq!(
-            "2200+ rapid".parse::<RatingRange>().unwrap().format,
-            Format::Rapid
+            "2200+ rapid".parse::<RatingRange>().unwrap().target,
+            Target::Format(Format::Rapid)
         );
     }
 
@@ -175,8 +401,8 @@ mod tests {
 
         assert_eq!(rr.min, None);
         assert_eq!(rr.max, Some(3000));
-        assert!(rr.is_match(Format::Blitz, 2999));
-        assert!(!rr.is_match(Format::Blitz, 3000));
+        assert!(rr.is_match(Provider::Lichess, Format::Blitz, 2999));
+        assert!(!rr.is_match(Provider::Lichess, Format::Blitz, 3000));
     }
 
     #[test]
@@ -185,9 +411,9 @@ mod tests {
 
         assert_eq!(rr.min, Some(2200));
         assert_eq!(rr.max, None);
-        assert!(rr.is_match(Format::Blitz, 2300));
-        assert!(rr.is_match(Format::Blitz, 2200));
-        assert!(!rr.is_match(Format::Blitz, 2199));
+        assert!(rr.is_match(Provider::Lichess, Format::Blitz, 2300));
+        assert!(rr.is_match(Provider::Lichess, Format::Blitz, 2200));
+        assert!(!rr.is_match(Provider::Lichess, Format::Blitz, 2199));
     }
 
     #[test]
@@ -197,9 +423,244 @@ mod tests {
         assert_eq!(rr.min, Some(1400));
         assert_eq!(rr.max, Some(1599));
 
-        assert!(!rr.is_match(Format::Bullet, 1399));
-        assert!(rr.is_match(Format::Bullet, 1400));
-        assert!(rr.is_match(Format::Bullet, 1599));
-        assert!(!rr.is_match(Format::Bullet, 1600));
+        assert!(!rr.is_match(Provider::Lichess, Format::Bullet, 1399));
+        assert!(rr.is_match(Provider::Lichess, Format::Bullet, 1400));
+        assert!(rr.is_match(Provider::Lichess, Format::Bullet, 1599));
+        assert!(!rr.is_match(Provider::Lichess, Format::Bullet, 1600));
+    }
+
+    #[test]
+    fn is_match_differentiates_on_provider() {
+        let rr = RatingRange::new(Format::Blitz, Some(10), None).with_provider(Provider::Chesscom);
+
+        assert!(rr.is_match(Provider::Chesscom, Format::Blitz, 15));
+        assert!(!rr.is_match(Provider::Lichess, Format::Blitz, 15));
+    }
+
+    #[test]
+    fn is_match_without_provider_matches_any_provider() {
+        let rr = RatingRange::new(Format::Blitz, Some(10), None);
+
+        assert!(rr.is_match(Provider::Chesscom, Format::Blitz, 15));
+        assert!(rr.is_match(Provider::Lichess, Format::Blitz, 15));
+    }
+
+    #[test]
+    fn parse_correctly_detects_provider() {
+        assert_eq!(
+            "1800+ blitz lichess"
+                .parse::<RatingRange>()
+                .unwrap()
+                .provider,
+            Some(Provider::Lichess)
+        );
+        assert_eq!(
+            "1800+ blitz chesscom"
+                .parse::<RatingRange>()
+                .unwrap()
+                .provider,
+            Some(Provider::Chesscom)
+        );
+        assert_eq!("1800+ blitz".parse::<RatingRange>().unwrap().provider, None);
+        assert!("1800+ blitz fics".parse::<RatingRange>().is_err());
+    }
+
+    #[test]
+    fn get_name_includes_provider() {
+        let rr =
+            RatingRange::new(Format::Blitz, Some(1800), None).with_provider(Provider::Chesscom);
+
+        assert_eq!(rr.get_name(), Some("1800+ blitz chesscom".to_string()));
+        assert_eq!(rr.get_name().unwrap().parse::<RatingRange>().unwrap(), rr);
+    }
+
+    #[test]
+    fn parse_correctly_detects_variants() {
+        assert_eq!(
+            "1500+ chess960".parse::<RatingRange>().unwrap().target,
+            Target::Format(Format::Chess960)
+        );
+        assert_eq!(
+            "U1200 kingofthehill".parse::<RatingRange>().unwrap().target,
+            Target::Format(Format::KingOfTheHill)
+        );
+        assert_eq!(
+            "1000-1199 puzzle".parse::<RatingRange>().unwrap().target,
+            Target::Format(Format::Puzzle)
+        );
+    }
+
+    #[test]
+    fn get_name_can_be_parsed_back_for_variants() {
+        let rr = RatingRange::new(Format::RacingKings, Some(1500), None);
+
+        assert_eq!(rr.get_name(), Some("1500+ racingkings".to_string()));
+        assert_eq!(rr.get_name().unwrap().parse::<RatingRange>().unwrap(), rr);
+    }
+
+    #[test]
+    fn parse_correctly_detects_peak_ranges() {
+        let rr = "peak 2000+ blitz".parse::<RatingRange>().unwrap();
+
+        assert!(rr.is_peak());
+        assert_eq!(rr.min, Some(2000));
+        assert_eq!(rr.target, Target::Format(Format::Blitz));
+        assert!(!"2000+ blitz".parse::<RatingRange>().unwrap().is_peak());
+    }
+
+    #[test]
+    fn get_name_includes_peak() {
+        let rr = RatingRange::new(Format::Blitz, Some(2000), None)
+            .into_peak()
+            .with_provider(Provider::Lichess);
+
+        assert_eq!(rr.get_name(), Some("peak 2000+ blitz lichess".to_string()));
+        assert_eq!(rr.get_name().unwrap().parse::<RatingRange>().unwrap(), rr);
+    }
+
+    #[test]
+    fn parse_accepts_dashes_and_comparisons() {
+        assert_eq!(
+            "1800–1999 blitz".parse::<RatingRange>().unwrap(),
+            RatingRange::new(Format::Blitz, Some(1800), Some(1999))
+        );
+        assert_eq!(
+            "<1200 blitz".parse::<RatingRange>().unwrap(),
+            RatingRange::new(Format::Blitz, None, Some(1200))
+        );
+        assert_eq!(
+            "<=1200 blitz".parse::<RatingRange>().unwrap(),
+            RatingRange::new(Format::Blitz, None, Some(1201))
+        );
+        assert_eq!(
+            ">=2000 blitz".parse::<RatingRange>().unwrap(),
+            RatingRange::new(Format::Blitz, Some(2000), None)
+        );
+        assert_eq!(
+            ">2000 blitz".parse::<RatingRange>().unwrap(),
+            RatingRange::new(Format::Blitz, Some(2001), None)
+        );
+    }
+
+    #[test]
+    fn parse_accepts_the_format_first() {
+        assert_eq!(
+            "Blitz 1800+".parse::<RatingRange>().unwrap(),
+            RatingRange::new(Format::Blitz, Some(1800), None)
+        );
+        assert_eq!(
+            "Rapid U1200 chesscom".parse::<RatingRange>().unwrap(),
+            RatingRange::new(Format::Rapid, None, Some(1200)).with_provider(Provider::Chesscom)
+        );
+        assert!("blitz 1800+ rapid".parse::<RatingRange>().is_err());
+    }
+
+    #[test]
+    fn parse_accepts_format_aliases() {
+        assert_eq!(
+            "2000+ classic".parse::<RatingRange>().unwrap().target,
+            Target::Format(Format::Classical)
+        );
+        assert_eq!(
+            "2000+ standard".parse::<RatingRange>().unwrap().target,
+            Target::Format(Format::Classical)
+        );
+    }
+
+    #[test]
+    fn parse_ignores_emoji_and_decoration() {
+        let rr = RatingRange::new(Format::Blitz, Some(1800), None);
+
+        assert_eq!("⚡ 1800+ Blitz ⚡".parse::<RatingRange>().unwrap(), rr);
+        assert_eq!("[Blitz: 1800+]".parse::<RatingRange>().unwrap(), rr);
+        assert_eq!("| 1800+ blitz |".parse::<RatingRange>().unwrap(), rr);
+        assert_eq!(
+            "『Peak 1800+ blitz』".parse::<RatingRange>().unwrap(),
+            rr.into_peak()
+        );
+    }
+
+    #[test]
+    fn parse_accepts_five_digit_numbers() {
+        assert_eq!(
+            "10000+ puzzle".parse::<RatingRange>().unwrap().min,
+            Some(10000)
+        );
+        assert!("99999+ puzzle".parse::<RatingRange>().is_err());
+    }
+
+    #[test]
+    fn parse_includes_upper_bounds_of_ranges() {
+        let rr = "1800-1999 blitz".parse::<RatingRange>().unwrap();
+
+        assert!(rr.is_match(Provider::Lichess, Format::Blitz, 1999));
+        assert!(!rr.is_match(Provider::Lichess, Format::Blitz, 2000));
+        assert!("2000-1800 blitz".parse::<RatingRange>().is_err());
+    }
+
+    #[test]
+    fn parse_keeps_round_upper_bounds_of_existing_roles() {
+        let rr = "1400-1600 bullet".parse::<RatingRange>().unwrap();
+
+        assert!(rr.is_match(Provider::Lichess, Format::Bullet, 1600));
+        assert!(!rr.is_match(Provider::Lichess, Format::Bullet, 1601));
+    }
+
+    #[test]
+    fn parse_detects_aggregates() {
+        let rr = "1800+ best".parse::<RatingRange>().unwrap();
+        assert_eq!(rr.target, Target::Best);
+        assert_eq!(rr.get_name().unwrap(), "1800+ best");
+
+        assert_eq!(
+            "Average 1500-1699".parse::<RatingRange>().unwrap().target,
+            Target::Average
+        );
+        assert_eq!(
+            "1500+ avg"
+                .parse::<RatingRange>()
+                .unwrap()
+                .get_name()
+                .unwrap(),
+            "1500+ average"
+        );
+        assert_eq!(
+            "peak 2000+ all".parse::<RatingRange>().unwrap().target,
+            Target::All
+        );
+    }
+
+    #[test]
+    fn aggregates_only_consider_enabled_formats() {
+        let ratings = [
+            (Provider::Lichess, Format::Blitz, 1900),
+            (Provider::Lichess, Format::Rapid, 1700),
+            (Provider::Lichess, Format::Bullet, 2500),
+        ];
+        let formats = [Format::Blitz, Format::Rapid];
+
+        let best = RatingRange::new(Target::Best, Some(1800), None);
+        assert!(best.matches_ratings(ratings, &formats));
+        assert!(!best.matches_ratings(ratings, &[Format::Rapid]));
+
+        let average = RatingRange::new(Target::Average, Some(1800), Some(1899));
+        assert!(average.matches_ratings(ratings, &formats));
+        assert!(!average.matches_ratings(ratings, &[]));
+
+        let all = RatingRange::new(Target::All, Some(1700), None);
+        assert!(all.matches_ratings(ratings, &formats));
+        assert!(!all.matches_ratings(ratings, &[Format::Blitz, Format::Classical]));
+        assert!(!all.matches_ratings(ratings, &[]));
+    }
+
+    #[test]
+    fn aggregates_respect_providers() {
+        let ratings = [
+            (Provider::Lichess, Format::Blitz, 2200),
+            (Provider::Chesscom, Format::Blitz, 1500),
+        ];
+        let rr = RatingRange::new(Target::Best, Some(1800), None).with_provider(Provider::Chesscom);
+
+        assert!(!rr.matches_ratings(ratings, &[Format::Blitz]));
     }
 }
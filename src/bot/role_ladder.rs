@@ -0,0 +1,196 @@
+use super::rating_range::{RatingRange, Target};
+use thiserror::Error;
+
+/// Most roles created by a single ladder, to keep well under Discord's limit of 250 roles
+pub const MAX_LADDER_ROLES: usize = 25;
+
+// The gradient goes from blue for the lowest ratings to red for the highest ones
+const LOW_COLOUR: (u8, u8, u8) = (0x34, 0x98, 0xdb);
+const HIGH_COLOUR: (u8, u8, u8) = (0xe7, 0x4c, 0x3c);
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum LadderError {
+    #[error("the step must be a positive multiple of 10")]
+    InvalidStep,
+    #[error("the lowest rating must be a multiple of 10, and can't be negative")]
+    InvalidStart,
+    #[error("the highest rating must be above the lowest one")]
+    InvalidEnd,
+    #[error(
+        "the ladder would have {0} roles, but at most {} can be created",
+        MAX_LADDER_ROLES
+    )]
+    TooManyRoles(usize),
+}
+
+/// Rating role of a generated ladder
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LadderRole {
+    pub range: RatingRange,
+    pub name: String,
+    pub colour: u64,
+}
+
+fn gradient(index: usize, count: usize) -> u64 {
+    let blend = |low: u8, high: u8| {
+        let (low, high) = (low as i64, high as i64);
+        let value = match count {
+            0 | 1 => high,
+            _ => low + (high - low) * index as i64 / (count - 1) as i64,
+        };
+        value as u64
+    };
+
+    (blend(LOW_COLOUR.0, HIGH_COLOUR.0) << 16)
+        | (blend(LOW_COLOUR.1, HIGH_COLOUR.1) << 8)
+        | blend(LOW_COLOUR.2, HIGH_COLOUR.2)
+}
+
+/// Builds a ladder of rating roles for `target`, from `from` to `to` by steps of `step` points
+///
+/// Roles are returned from the lowest rating to the highest, and the last one is open-ended,
+/// e.g. `800-999 blitz`, `1000-1199 blitz`, …, `2400+ blitz`. Bounds are kept to multiples of 10,
+/// and upper bounds stop one point short of the next role, as they are inclusive.
+pub fn ladder<T>(target: T, step: i16, from: i16, to: i16) -> Result<Vec<LadderRole>, LadderError>
+where
+    T: Into<Target>,
+{
+    trace!("ladder() called");
+    let target = target.into();
+
+    if step <= 0 || step % 10 != 0 {
+        return Err(LadderError::InvalidStep);
+    }
+
+    if from < 0 || from % 10 != 0 {
+        return Err(LadderError::InvalidStart);
+    }
+
+    if to <= from {
+        return Err(LadderError::InvalidEnd);
+    }
+
+    // One role per step started below `to`, and the open-ended one
+    let count = ((to - from - 1) / step) as usize + 2;
+    if count > MAX_LADDER_ROLES {
+        return Err(LadderError::TooManyRoles(count));
+    }
+
+    let ranges = (0..count - 1)
+        .map(|i| {
+            let min = from + step * i as i16;
+            let max = min.saturating_add(step).min(to) - 1;
+            RatingRange::new(target, Some(min), Some(max))
+        })
+        .chain(std::iter::once(RatingRange::new(target, Some(to), None)));
+
+    Ok(ranges
+        .enumerate()
+        .map(|(i, range)| LadderRole {
+            name: range.get_name().unwrap_or_default(),
+            colour: gradient(i, count),
+            range,
+        })
+        .collect())
+}
+
+/// Positions moving the `created` roles right under the highest role of the bot, at `bot_position`
+///
+/// `roles` are the IDs and positions of the roles of the guild, without `@everyone`. Only the
+/// roles below the bot are given a position, as it can't move the others, and `created` keep
+/// their order so that the ladder stays sorted by rating.
+pub fn ladder_positions(
+    roles: &[(u64, i64)],
+    created: &[u64],
+    bot_position: i64,
+) -> Vec<(u64, u64)> {
+    trace!("ladder_positions() called");
+    let mut below: Vec<(u64, i64)> = roles
+        .iter()
+        .filter(|(id, position)| *position < bot_position && !created.contains(id))
+        .copied()
+        .collect();
+    below.sort_by_key(|&(id, position)| (position, id));
+
+    below
+        .into_iter()
+        .map(|(id, _)| id)
+        .chain(created.iter().copied())
+        .zip(1..)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lichess::Format;
+
+    #[test]
+    fn ladder_names_roles_that_parse_back() {
+        let roles = ladder(Format::Blitz, 200, 800, 2400).unwrap();
+        let names: Vec<&str> = roles.iter().map(|r| r.name.as_str()).collect();
+
+        assert_eq!(names.len(), 9);
+        assert_eq!(names[0], "800-999 blitz");
+        assert_eq!(names[7], "2200-2399 blitz");
+        assert_eq!(names[8], "2400+ blitz");
+
+        for role in roles {
+            assert_eq!(role.name.parse::<RatingRange>().unwrap(), role.range);
+        }
+    }
+
+    #[test]
+    fn ladder_shortens_the_last_bounded_step() {
+        let roles = ladder(Format::Rapid, 300, 1000, 1500).unwrap();
+        let names: Vec<&str> = roles.iter().map(|r| r.name.as_str()).collect();
+
+        assert_eq!(
+            names,
+            vec!["1000-1299 rapid", "1300-1499 rapid", "1500+ rapid"]
+        );
+    }
+
+    #[test]
+    fn ladder_colours_go_from_blue_to_red() {
+        let roles = ladder(Target::Best, 100, 1000, 1500).unwrap();
+
+        assert_eq!(roles.first().unwrap().colour, 0x3498db);
+        assert_eq!(roles.last().unwrap().colour, 0xe74c3c);
+    }
+
+    #[test]
+    fn ladder_rejects_invalid_parameters() {
+        assert_eq!(
+            ladder(Format::Blitz, 0, 800, 2400),
+            Err(LadderError::InvalidStep)
+        );
+        assert_eq!(
+            ladder(Format::Blitz, 105, 800, 2400),
+            Err(LadderError::InvalidStep)
+        );
+        assert_eq!(
+            ladder(Format::Blitz, 100, 801, 2400),
+            Err(LadderError::InvalidStart)
+        );
+        assert_eq!(
+            ladder(Format::Blitz, 100, 800, 800),
+            Err(LadderError::InvalidEnd)
+        );
+        assert_eq!(
+            ladder(Format::Blitz, 10, 800, 2400),
+            Err(LadderError::TooManyRoles(161))
+        );
+    }
+
+    #[test]
+    fn ladder_positions_put_created_roles_under_the_bot() {
+        // The bot's role is 10, and new roles were created at the bottom
+        let roles = [(1, 1), (2, 1), (3, 2), (4, 3), (10, 4), (11, 5)];
+
+        assert_eq!(
+            ladder_positions(&roles, &[1, 2], 4),
+            vec![(3, 1), (4, 2), (1, 3), (2, 4)]
+        );
+    }
+}
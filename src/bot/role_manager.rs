@@ -1,5 +1,10 @@
-use super::rating_range::RatingRange;
-use crate::lichess::Format;
+use super::{
+    member_profile::MemberProfile,
+    preflight::PreflightIssue,
+    range_check::{check_ranges, RangeIssue},
+    role_rule::RoleRule,
+};
+use crate::{lichess::Format, ratings::RatingPolicy};
 use std::{
     collections::HashMap,
     sync::{Arc, Mutex},
@@ -7,7 +12,8 @@ use std::{
 
 #[derive(Debug, Clone)]
 pub struct RoleManager {
-    guild_roles: Arc<Mutex<HashMap<u64, HashMap<u64, RatingRange>>>>,
+    guild_roles: Arc<Mutex<HashMap<u64, HashMap<u64, RoleRule>>>>,
+    preflight_issues: Arc<Mutex<HashMap<u64, Vec<PreflightIssue>>>>,
 }
 
 impl RoleManager {
@@ -15,45 +21,120 @@ impl RoleManager {
         trace!("RoleManager::new() called");
         RoleManager {
             guild_roles: Default::default(),
+            preflight_issues: Default::default(),
         }
     }
 
-    /// Adds a new rating range role for the specific `guild_id`
+    /// Adds a new role managed through `rule` for the specific `guild_id`
     ///
     /// If the `guild_id` does not exist in the role manager, it is automatically created.
-    pub fn add_rating_range<R>(&mut self, guild_id: u64, role_id: u64, rating: R)
+    pub fn add_role<R>(&mut self, guild_id: u64, role_id: u64, rule: R)
     where
-        R: Into<RatingRange>,
+        R: Into<RoleRule>,
     {
-        trace!("RoleManager::add_rating_range() called");
+        trace!("RoleManager::add_role() called");
         let mut lock = self.guild_roles.lock().unwrap();
 
         if let Some(gr) = lock.get_mut(&guild_id) {
-            gr.insert(role_id, rating.into());
+            gr.insert(role_id, rule.into());
         } else {
-            lock.insert(
-                guild_id,
-                [(role_id, rating.into())].iter().cloned().collect(),
-            );
+            lock.insert(guild_id, [(role_id, rule.into())].iter().cloned().collect());
         }
     }
 
-    pub fn remove_role(&mut self, guild_id: u64, role_id: u64) {
+    /// Lists the guilds in which roles are managed
+    pub fn get_guild_ids(&self) -> Vec<u64> {
+        trace!("RoleManager::get_guild_ids() called");
+        self.guild_roles.lock().unwrap().keys().copied().collect()
+    }
+
+    /// Lists the roles managed in `guild_id` along with their rule
+    pub fn get_roles(&self, guild_id: u64) -> Vec<(u64, RoleRule)> {
+        trace!("RoleManager::get_roles() called");
+        self.guild_roles
+            .lock()
+            .unwrap()
+            .get(&guild_id)
+            .map(|gr| gr.iter().map(|(&k, v)| (k, v.clone())).collect())
+            .unwrap_or_default()
+    }
+
+    /// Stops managing `role_id`, returning whether it was managed
+    pub fn remove_role(&mut self, guild_id: u64, role_id: u64) -> bool {
         trace!("RoleManager::remove_role() called");
-        if let Some(gr) = self.guild_roles.lock().unwrap().get_mut(&guild_id) {
-            gr.remove(&role_id);
+        match self.guild_roles.lock().unwrap().get_mut(&guild_id) {
+            Some(gr) => gr.remove(&role_id).is_some(),
+            None => false,
         }
     }
 
     pub fn delete_guild(&mut self, guild_id: u64) {
         trace!("RoleManager::delete_guild() called");
         self.guild_roles.lock().unwrap().remove(&guild_id);
+        self.preflight_issues.lock().unwrap().remove(&guild_id);
+    }
+
+    /// Records the issues found by the last preflight check of `guild_id`
+    ///
+    /// Returns whether they differ from the previous ones.
+    pub fn set_preflight_issues(&mut self, guild_id: u64, issues: Vec<PreflightIssue>) -> bool {
+        trace!("RoleManager::set_preflight_issues() called");
+        let mut lock = self.preflight_issues.lock().unwrap();
+        let previous = lock.insert(guild_id, issues.clone()).unwrap_or_default();
+
+        previous != issues
     }
 
+    pub fn get_preflight_issues(&self, guild_id: u64) -> Vec<PreflightIssue> {
+        trace!("RoleManager::get_preflight_issues() called");
+        self.preflight_issues
+            .lock()
+            .unwrap()
+            .get(&guild_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Keeps the roles of `role_ids` the bot is able to give or remove in `guild_id`
+    pub fn filter_manageable(&self, guild_id: u64, role_ids: Vec<u64>) -> Vec<u64> {
+        trace!("RoleManager::filter_manageable() called");
+        let issues = self.get_preflight_issues(guild_id);
+
+        if issues.contains(&PreflightIssue::MissingManageRoles) {
+            return vec![];
+        }
+
+        role_ids
+            .into_iter()
+            .filter(|&r| !issues.iter().any(|i| i.role_id() == Some(r)))
+            .collect()
+    }
+
+    /// Keeps the roles of `role_ids` that aren't given for a rating range, such as title, patron
+    /// and team roles
+    pub fn without_rating_roles(&self, guild_id: u64, role_ids: Vec<u64>) -> Vec<u64> {
+        trace!("RoleManager::without_rating_roles() called");
+        let lock = self.guild_roles.lock().unwrap();
+        let rules = match lock.get(&guild_id) {
+            Some(rules) => rules,
+            None => return role_ids,
+        };
+
+        role_ids
+            .into_iter()
+            .filter(|r| !matches!(rules.get(r), Some(RoleRule::Rating(_))))
+            .collect()
+    }
+
+    /// Finds the roles whose rule matches `profile`, ignoring the ratings refused by `policy`
+    ///
+    /// Aggregate rating roles are computed over the guild's enabled `formats`.
     pub fn find_rating_range_roles(
         &self,
         guild_id: u64,
-        ratings: &HashMap<Format, i16>,
+        profile: &MemberProfile,
+        policy: &RatingPolicy,
+        formats: &[Format],
     ) -> Vec<u64> {
         trace!("RoleManager::find_rating_range_role() called");
         self.guild_roles
@@ -63,18 +144,57 @@ impl RoleManager {
             .map(|gr| {
                 gr.iter()
                     .filter_map(|(&k, v)| {
-                        for (format, rating) in ratings {
-                            if v.is_match(*format, *rating) {
-                                return Some(k);
-                            }
+                        if v.is_match(profile, policy, formats) {
+                            Some(k)
+                        } else {
+                            None
                         }
-                        None
                     })
                     .collect()
             })
             .unwrap_or_default()
     }
 
+    /// Finds the rating roles `profile` would match if their ranges were widened by `grace` points
+    pub fn find_grace_roles(
+        &self,
+        guild_id: u64,
+        profile: &MemberProfile,
+        policy: &RatingPolicy,
+        formats: &[Format],
+        grace: i16,
+    ) -> Vec<u64> {
+        trace!("RoleManager::find_grace_roles() called");
+        self.get_roles(guild_id)
+            .into_iter()
+            .filter_map(|(role_id, rule)| match rule {
+                RoleRule::Rating(rr) => {
+                    if RoleRule::from(rr.widen(grace)).is_match(profile, policy, formats) {
+                        Some(role_id)
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Looks for overlapping, missing and unreachable rating ranges in `guild_id`
+    pub fn check_rating_ranges(&self, guild_id: u64) -> Vec<RangeIssue> {
+        trace!("RoleManager::check_rating_ranges() called");
+        let ranges: Vec<_> = self
+            .get_roles(guild_id)
+            .into_iter()
+            .filter_map(|(role_id, rule)| match rule {
+                RoleRule::Rating(rr) => Some((role_id, rr)),
+                _ => None,
+            })
+            .collect();
+
+        check_ranges(&ranges)
+    }
+
     pub fn other_rating_range_roles<R>(&self, guild_id: u64, role_ids: R) -> Vec<u64>
     where
         R: AsRef<[u64]>,
@@ -125,14 +245,30 @@ impl RoleManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        bot::{account_role::AccountRole, rating_range::RatingRange},
+        ratings::{Provider, Rating, Ratings, Title},
+    };
+
+    fn profile(provider: Provider, format: Format, rating: i16) -> MemberProfile {
+        MemberProfile {
+            ratings: [(provider, format, rating)].iter().cloned().collect(),
+            ..Default::default()
+        }
+    }
 
     #[test]
     fn find_rating_range_role_can_be_called_on_an_empty_manager() {
         let rm = RoleManager::new();
 
         assert_eq!(
-            rm.find_rating_range_roles(0, &[(Format::Blitz, 15)].iter().cloned().collect())
-                .len(),
+            rm.find_rating_range_roles(
+                0,
+                &profile(Provider::Lichess, Format::Blitz, 15),
+                &RatingPolicy::default(),
+                &[]
+            )
+            .len(),
             0
         );
     }
@@ -141,21 +277,49 @@ mod tests {
     fn find_rating_range_returns_all_ranges_that_match() {
         let mut rm = RoleManager::new();
 
-        rm.add_rating_range(0, 123, RatingRange::new(Format::Blitz, Some(10), Some(20)));
-        rm.add_rating_range(0, 345, RatingRange::new(Format::Blitz, Some(10), Some(30)));
-        rm.add_rating_range(
+        rm.add_role(0, 123, RatingRange::new(Format::Blitz, Some(10), Some(20)));
+        rm.add_role(0, 345, RatingRange::new(Format::Blitz, Some(10), Some(30)));
+        rm.add_role(
             0,
             456,
             RatingRange::new(Format::Classical, Some(10), Some(30)),
         );
 
-        let result =
-            rm.find_rating_range_roles(0, &[(Format::Blitz, 15)].iter().cloned().collect());
+        let result = rm.find_rating_range_roles(
+            0,
+            &profile(Provider::Lichess, Format::Blitz, 15),
+            &RatingPolicy::default(),
+            &[],
+        );
         assert!(result.contains(&123));
         assert!(result.contains(&345));
         assert!(!result.contains(&456));
     }
 
+    #[test]
+    fn find_rating_range_respects_providers() {
+        let mut rm = RoleManager::new();
+
+        rm.add_role(
+            0,
+            123,
+            RatingRange::new(Format::Blitz, Some(10), None).with_provider(Provider::Lichess),
+        );
+        rm.add_role(
+            0,
+            345,
+            RatingRange::new(Format::Blitz, Some(10), None).with_provider(Provider::Chesscom),
+        );
+
+        let result = rm.find_rating_range_roles(
+            0,
+            &profile(Provider::Chesscom, Format::Blitz, 15),
+            &RatingPolicy::default(),
+            &[],
+        );
+        assert_eq!(result, vec![345]);
+    }
+
     #[test]
     fn remove_role_can_be_called_on_an_empty_manager() {
         let mut rm = RoleManager::new();
@@ -167,18 +331,28 @@ mod tests {
     fn remove_role_correctly_removes_roles() {
         let mut rm = RoleManager::new();
 
-        rm.add_rating_range(0, 123, RatingRange::new(Format::Blitz, Some(10), Some(20)));
+        rm.add_role(0, 123, RatingRange::new(Format::Blitz, Some(10), Some(20)));
 
         assert_eq!(
-            rm.find_rating_range_roles(0, &[(Format::Blitz, 15)].iter().cloned().collect()),
+            rm.find_rating_range_roles(
+                0,
+                &profile(Provider::Lichess, Format::Blitz, 15),
+                &RatingPolicy::default(),
+                &[]
+            ),
             vec![123]
         );
 
         rm.remove_role(0, 123);
 
         assert_eq!(
-            rm.find_rating_range_roles(0, &[(Format::Blitz, 15)].iter().cloned().collect())
-                .len(),
+            rm.find_rating_range_roles(
+                0,
+                &profile(Provider::Lichess, Format::Blitz, 15),
+                &RatingPolicy::default(),
+                &[]
+            )
+            .len(),
             0
         );
     }
@@ -194,9 +368,142 @@ mod tests {
     fn other_rating_range_roles_returns_other_roles() {
         let mut rm = RoleManager::new();
 
-        rm.add_rating_range(0, 123, RatingRange::new(Format::Blitz, Some(10), Some(19)));
-        rm.add_rating_range(0, 345, RatingRange::new(Format::Bullet, Some(20), Some(30)));
+        rm.add_role(0, 123, RatingRange::new(Format::Blitz, Some(10), Some(19)));
+        rm.add_role(0, 345, RatingRange::new(Format::Bullet, Some(20), Some(30)));
 
         assert_eq!(rm.other_rating_range_roles(0, &[123]), vec![345]);
     }
+
+    #[test]
+    fn find_rating_range_applies_the_guild_policy() {
+        let mut rm = RoleManager::new();
+        rm.add_role(0, 123, RatingRange::new(Format::Blitz, Some(10), None));
+
+        let profile = MemberProfile {
+            ratings: [(
+                Provider::Lichess,
+                Format::Blitz,
+                Rating::new(15, Some(150), 0, 3, true),
+            )]
+            .iter()
+            .cloned()
+            .collect(),
+            ..Default::default()
+        };
+
+        let mut policy = RatingPolicy::default();
+        assert!(rm
+            .find_rating_range_roles(0, &profile, &policy, &[])
+            .is_empty());
+
+        policy.set_allow_provisional(true);
+        assert_eq!(
+            rm.find_rating_range_roles(0, &profile, &policy, &[]),
+            vec![123]
+        );
+
+        policy.set_min_games(Format::Blitz, 10);
+        assert!(rm
+            .find_rating_range_roles(0, &profile, &policy, &[])
+            .is_empty());
+    }
+
+    #[test]
+    fn find_rating_range_matches_peak_ranges_against_peaks() {
+        let mut rm = RoleManager::new();
+        rm.add_role(0, 123, RatingRange::new(Format::Blitz, Some(20), None));
+        rm.add_role(
+            0,
+            345,
+            RatingRange::new(Format::Blitz, Some(20), None).into_peak(),
+        );
+
+        let profile = MemberProfile {
+            peaks: [(Provider::Lichess, Format::Blitz, 25)]
+                .iter()
+                .cloned()
+                .collect::<Ratings>(),
+            ..profile(Provider::Lichess, Format::Blitz, 15)
+        };
+
+        let result = rm.find_rating_range_roles(0, &profile, &RatingPolicy::default(), &[]);
+        assert_eq!(result, vec![345]);
+    }
+
+    #[test]
+    fn find_rating_range_matches_account_roles() {
+        let mut rm = RoleManager::new();
+        rm.add_role(0, 123, RatingRange::new(Format::Blitz, Some(20), None));
+        rm.add_role(0, 345, AccountRole::Title(Title::IM));
+        rm.add_role(0, 456, AccountRole::Patron);
+
+        let profile = MemberProfile {
+            title: Some(Title::IM),
+            ..profile(Provider::Lichess, Format::Blitz, 15)
+        };
+
+        let result = rm.find_rating_range_roles(0, &profile, &RatingPolicy::default(), &[]);
+        assert_eq!(result, vec![345]);
+        assert_eq!(rm.other_rating_range_roles(0, &result).len(), 2);
+    }
+
+    #[test]
+    fn flagged_members_keep_their_non_rating_roles() {
+        let mut rm = RoleManager::new();
+        rm.add_role(0, 123, RatingRange::new(Format::Blitz, Some(10), None));
+        rm.add_role(0, 345, AccountRole::Title(Title::IM));
+        rm.add_role(0, 456, RoleRule::Team("liro-fans".to_string()));
+
+        let profile = MemberProfile {
+            title: Some(Title::IM),
+            teams: vec!["liro-fans".to_string()],
+            ..profile(Provider::Lichess, Format::Blitz, 15)
+        };
+
+        let mut matched = rm.find_rating_range_roles(0, &profile, &RatingPolicy::default(), &[]);
+        matched.sort_unstable();
+        assert_eq!(matched, vec![123, 345, 456]);
+
+        let kept = rm.without_rating_roles(0, matched);
+        assert_eq!(kept, vec![345, 456]);
+        assert_eq!(rm.other_rating_range_roles(0, &kept), vec![123]);
+    }
+
+    #[test]
+    fn find_grace_roles_widens_rating_ranges() {
+        let mut rm = RoleManager::new();
+        rm.add_role(0, 123, RatingRange::new(Format::Blitz, Some(20), None));
+        rm.add_role(0, 345, RatingRange::new(Format::Blitz, None, Some(10)));
+        rm.add_role(0, 456, AccountRole::Patron);
+
+        let profile = MemberProfile {
+            patron: true,
+            ..profile(Provider::Lichess, Format::Blitz, 15)
+        };
+        let policy = RatingPolicy::default();
+
+        assert!(rm.find_grace_roles(0, &profile, &policy, &[], 0).is_empty());
+        assert_eq!(rm.find_grace_roles(0, &profile, &policy, &[], 5), vec![123]);
+
+        let mut result = rm.find_grace_roles(0, &profile, &policy, &[], 6);
+        result.sort_unstable();
+        assert_eq!(result, vec![123, 345]);
+    }
+
+    #[test]
+    fn filter_manageable_skips_roles_with_preflight_issues() {
+        let mut rm = RoleManager::new();
+        assert_eq!(rm.filter_manageable(0, vec![123, 345]), vec![123, 345]);
+
+        let issue = PreflightIssue::AboveBot {
+            role: 345,
+            name: "2000+ blitz".to_string(),
+        };
+        assert!(rm.set_preflight_issues(0, vec![issue.clone()]));
+        assert!(!rm.set_preflight_issues(0, vec![issue]));
+        assert_eq!(rm.filter_manageable(0, vec![123, 345]), vec![123]);
+
+        rm.set_preflight_issues(0, vec![PreflightIssue::MissingManageRoles]);
+        assert!(rm.filter_manageable(0, vec![123, 345]).is_empty());
+    }
 }
@@ -0,0 +1,176 @@
+use super::{
+    account_role::AccountRole,
+    member_profile::MemberProfile,
+    rating_range::RatingRange,
+    rule_expr::{RuleError, RuleExpr},
+};
+use crate::{lichess::Format, ratings::RatingPolicy};
+use std::{fmt, str::FromStr};
+
+/// Condition a member must fulfil to be given a role managed by the bot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoleRule {
+    Rating(RatingRange),
+    Account(AccountRole),
+    /// Membership of a lichess team, identified by its ID
+    Team(String),
+    /// Composite rule, only available through bindings
+    Expression(RuleExpr),
+}
+
+impl RoleRule {
+    /// Checks whether `profile` fulfils the rule
+    ///
+    /// Ratings refused by `policy` are ignored. Peak ratings and account roles aren't subject to
+    /// the policy. Aggregate rating rules only consider the guild's enabled `formats`.
+    pub fn is_match(
+        &self,
+        profile: &MemberProfile,
+        policy: &RatingPolicy,
+        formats: &[Format],
+    ) -> bool {
+        trace!("RoleRule::is_match() called");
+        match self {
+            RoleRule::Rating(rr) if rr.is_peak() => rr.matches_ratings(
+                profile
+                    .peaks
+                    .iter()
+                    .map(|(provider, format, peak)| (provider, format, peak.rating())),
+                formats,
+            ),
+            RoleRule::Rating(rr) => rr.matches_ratings(
+                profile
+                    .ratings
+                    .iter()
+                    .filter(|(_, format, rating)| policy.check(*format, rating).is_ok())
+                    .map(|(provider, format, rating)| (provider, format, rating.rating())),
+                formats,
+            ),
+            RoleRule::Account(AccountRole::Title(title)) => profile.title == Some(*title),
+            RoleRule::Account(AccountRole::Patron) => profile.patron,
+            RoleRule::Account(AccountRole::Verified) => profile.verified,
+            RoleRule::Team(team_id) => profile.teams.contains(team_id),
+            RoleRule::Expression(expr) => expr.is_match(profile, policy, formats),
+        }
+    }
+
+    pub fn get_name(&self) -> Option<String> {
+        match self {
+            RoleRule::Rating(rr) => rr.get_name(),
+            RoleRule::Account(role) => Some(role.get_name()),
+            RoleRule::Team(team_id) => Some(format!("lichess team {}", team_id)),
+            RoleRule::Expression(expr) => Some(expr.to_string()),
+        }
+    }
+
+    /// Parses a rule bound to a role, which may also be a composite rule
+    ///
+    /// Role names are never read as composite rules, so that a role doesn't get managed by
+    /// accident.
+    pub fn parse_binding(s: &str) -> Result<Self, RuleError> {
+        trace!("RoleRule::parse_binding() called");
+        match s.parse::<RoleRule>() {
+            Ok(rule) => Ok(rule),
+            Err(_) => Ok(RoleRule::Expression(s.parse()?)),
+        }
+    }
+}
+
+impl From<RatingRange> for RoleRule {
+    fn from(rr: RatingRange) -> Self {
+        RoleRule::Rating(rr)
+    }
+}
+
+impl From<AccountRole> for RoleRule {
+    fn from(role: AccountRole) -> Self {
+        RoleRule::Account(role)
+    }
+}
+
+impl fmt::Display for RoleRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RoleRule::Rating(rr) => rr.fmt(f),
+            RoleRule::Account(role) => role.fmt(f),
+            RoleRule::Team(team_id) => write!(f, "Team<{}>", team_id),
+            RoleRule::Expression(expr) => write!(f, "Expression<{}>", expr),
+        }
+    }
+}
+
+impl FromStr for RoleRule {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<RatingRange>()
+            .map(RoleRule::from)
+            .or_else(|_| s.parse::<AccountRole>().map(RoleRule::from))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lichess::Format, ratings::Title};
+
+    #[test]
+    fn parse_detects_rating_ranges_and_account_roles() {
+        assert_eq!(
+            "1800+ blitz".parse::<RoleRule>().unwrap(),
+            RoleRule::Rating(RatingRange::new(Format::Blitz, Some(1800), None))
+        );
+        assert_eq!(
+            "IM".parse::<RoleRule>().unwrap(),
+            RoleRule::Account(AccountRole::Title(Title::IM))
+        );
+        assert!("Moderators".parse::<RoleRule>().is_err());
+    }
+
+    #[test]
+    fn only_bindings_are_parsed_as_composite_rules() {
+        let rule = "blitz >= 1500 and rapid >= 1500";
+
+        assert!(rule.parse::<RoleRule>().is_err());
+        assert!(matches!(
+            RoleRule::parse_binding(rule),
+            Ok(RoleRule::Expression(_))
+        ));
+        assert_eq!(
+            RoleRule::parse_binding("1800+ blitz"),
+            Ok(RoleRule::Rating(RatingRange::new(
+                Format::Blitz,
+                Some(1800),
+                None
+            )))
+        );
+        assert!(RoleRule::parse_binding("Moderators").is_err());
+    }
+
+    #[test]
+    fn account_roles_match_the_profile() {
+        let profile = MemberProfile {
+            title: Some(Title::FM),
+            patron: true,
+            ..Default::default()
+        };
+        let policy = RatingPolicy::default();
+
+        assert!(RoleRule::from(AccountRole::Title(Title::FM)).is_match(&profile, &policy, &[]));
+        assert!(!RoleRule::from(AccountRole::Title(Title::IM)).is_match(&profile, &policy, &[]));
+        assert!(RoleRule::from(AccountRole::Patron).is_match(&profile, &policy, &[]));
+        assert!(!RoleRule::from(AccountRole::Verified).is_match(&profile, &policy, &[]));
+    }
+
+    #[test]
+    fn team_rules_match_team_members() {
+        let profile = MemberProfile {
+            teams: vec!["lichess-swiss".to_string()],
+            ..Default::default()
+        };
+        let policy = RatingPolicy::default();
+
+        assert!(RoleRule::Team("lichess-swiss".to_string()).is_match(&profile, &policy, &[]));
+        assert!(!RoleRule::Team("coders".to_string()).is_match(&profile, &policy, &[]));
+    }
+}
@@ -0,0 +1,611 @@
+use super::{
+    member_profile::MemberProfile,
+    rating_range::{RatingRange, Target},
+    role_rule::RoleRule,
+};
+use crate::{
+    lichess::Format,
+    ratings::{Provider, RatingPolicy, Title},
+};
+use std::{fmt, str::FromStr};
+use thiserror::Error;
+
+// Nesting allowed in a rule, so that a hostile rule can't exhaust the stack
+const MAX_DEPTH: usize = 32;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum RuleError {
+    #[error("the rule ends too early")]
+    UnexpectedEnd,
+    #[error("I didn't expect `{0}` there")]
+    UnexpectedToken(String),
+    #[error("`{0}` isn't a rating, `games`, `title` or `age`")]
+    UnknownSubject(String),
+    #[error("`{0}` can't be compared to `{1}`")]
+    InvalidValue(String, String),
+    #[error("titles can only be compared with `=` or `!=`")]
+    InvalidTitleOperator,
+    #[error("the rule is nested too deeply")]
+    TooDeep,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Operator {
+    fn compare<T: Ord>(&self, left: T, right: T) -> bool {
+        match self {
+            Operator::Less => left < right,
+            Operator::LessOrEqual => left <= right,
+            Operator::Greater => left > right,
+            Operator::GreaterOrEqual => left >= right,
+            Operator::Equal => left == right,
+            Operator::NotEqual => left != right,
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Operator::Less => "<",
+            Operator::LessOrEqual => "<=",
+            Operator::Greater => ">",
+            Operator::GreaterOrEqual => ">=",
+            Operator::Equal => "=",
+            Operator::NotEqual => "!=",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Value of a member that numbers are compared to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subject {
+    /// Rating in a format, or an aggregate of the enabled formats
+    Rating {
+        target: Target,
+        provider: Option<Provider>,
+        peak: bool,
+    },
+    /// Rated games played in a format, or in every enabled format
+    Games {
+        format: Option<Format>,
+        provider: Option<Provider>,
+    },
+    /// Days since the oldest linked account was created
+    Age,
+}
+
+impl fmt::Display for Subject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Subject::Rating {
+                target,
+                provider,
+                peak,
+            } => {
+                if *peak {
+                    write!(f, "peak ")?;
+                }
+                write!(f, "{}", target.key())?;
+                if let Some(provider) = provider {
+                    write!(f, " {}", provider)?;
+                }
+                Ok(())
+            }
+            Subject::Games { format, provider } => {
+                write!(f, "games")?;
+                if let Some(format) = format {
+                    write!(f, " {}", format.key())?;
+                }
+                if let Some(provider) = provider {
+                    write!(f, " {}", provider)?;
+                }
+                Ok(())
+            }
+            Subject::Age => write!(f, "age"),
+        }
+    }
+}
+
+/// Rule combining conditions on ratings, games played, titles and account age
+///
+/// Rules are written like `blitz >= 1500 and rapid >= 1500 and games >= 100`, and can use `and`,
+/// `or`, `not` and parentheses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleExpr {
+    And(Vec<RuleExpr>),
+    Or(Vec<RuleExpr>),
+    Not(Box<RuleExpr>),
+    Compare(Subject, Operator, i64),
+    Title(Operator, Title),
+}
+
+impl RuleExpr {
+    /// Checks whether `profile` fulfils the rule
+    ///
+    /// Current ratings refused by `policy` are ignored, and aggregates only consider the guild's
+    /// enabled `formats`, like plain rating ranges do.
+    pub fn is_match(
+        &self,
+        profile: &MemberProfile,
+        policy: &RatingPolicy,
+        formats: &[Format],
+    ) -> bool {
+        trace!("RuleExpr::is_match() called");
+        match self {
+            RuleExpr::And(exprs) => exprs.iter().all(|e| e.is_match(profile, policy, formats)),
+            RuleExpr::Or(exprs) => exprs.iter().any(|e| e.is_match(profile, policy, formats)),
+            RuleExpr::Not(expr) => !expr.is_match(profile, policy, formats),
+            RuleExpr::Compare(subject, operator, value) => {
+                compare(subject, *operator, *value, profile, policy, formats)
+            }
+            RuleExpr::Title(operator, title) => {
+                (profile.title == Some(*title)) == (*operator == Operator::Equal)
+            }
+        }
+    }
+}
+
+/// Range of ratings fulfilling `operator` and `value`, `None` for `!=`
+fn rating_range(target: Target, operator: Operator, value: i16) -> Option<RatingRange> {
+    // Ranges without a minimum exclude their maximum
+    let (min, max) = match operator {
+        Operator::Less => (None, Some(value)),
+        Operator::LessOrEqual => (None, Some(value.saturating_add(1))),
+        Operator::Greater => (Some(value.saturating_add(1)), None),
+        Operator::GreaterOrEqual => (Some(value), None),
+        Operator::Equal => (Some(value), Some(value)),
+        Operator::NotEqual => return None,
+    };
+
+    Some(RatingRange::new(target, min, max))
+}
+
+fn compare(
+    subject: &Subject,
+    operator: Operator,
+    value: i64,
+    profile: &MemberProfile,
+    policy: &RatingPolicy,
+    formats: &[Format],
+) -> bool {
+    match *subject {
+        Subject::Rating {
+            target,
+            provider,
+            peak,
+        } => {
+            // Values were checked to fit ratings when the rule was parsed
+            let value = value as i16;
+            let range = match rating_range(target, operator, value) {
+                Some(range) => range,
+                None => {
+                    return !compare(
+                        subject,
+                        Operator::Equal,
+                        value as i64,
+                        profile,
+                        policy,
+                        formats,
+                    )
+                }
+            };
+            let range = match provider {
+                Some(provider) => range.with_provider(provider),
+                None => range,
+            };
+            let range = if peak { range.into_peak() } else { range };
+
+            RoleRule::from(range).is_match(profile, policy, formats)
+        }
+        Subject::Games { format, provider } => {
+            let games: i64 = profile
+                .ratings
+                .iter()
+                .filter(|(_, f, _)| match format {
+                    Some(format) => *f == format,
+                    None => formats.contains(f),
+                })
+                .filter(|(p, _, _)| !matches!(provider, Some(provider) if provider != *p))
+                .map(|(_, _, rating)| rating.games() as i64)
+                .sum();
+
+            operator.compare(games, value)
+        }
+        Subject::Age => match profile.account_age {
+            Some(age) => operator.compare(age as i64, value),
+            None => false,
+        },
+    }
+}
+
+impl fmt::Display for RuleExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // `and` binds tighter than `or`, so only the operands of `and` and `not` need parentheses
+        let operand = |expr: &RuleExpr, tight: bool| match expr {
+            RuleExpr::Or(_) => format!("({})", expr),
+            RuleExpr::And(_) if tight => format!("({})", expr),
+            _ => expr.to_string(),
+        };
+
+        match self {
+            RuleExpr::And(exprs) => {
+                let exprs: Vec<String> = exprs.iter().map(|e| operand(e, false)).collect();
+                write!(f, "{}", exprs.join(" and "))
+            }
+            RuleExpr::Or(exprs) => {
+                let exprs: Vec<String> = exprs.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", exprs.join(" or "))
+            }
+            RuleExpr::Not(expr) => write!(f, "not {}", operand(expr, true)),
+            RuleExpr::Compare(subject, operator, value) => {
+                write!(f, "{} {} {}", subject, operator, value)
+            }
+            RuleExpr::Title(operator, title) => write!(f, "title {} {}", operator, title),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Operator(Operator),
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{}", word),
+            Token::Operator(operator) => operator.fmt(f),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, RuleError> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            _ if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '<' | '>' | '=' | '!' => {
+                let equal = chars.next_if_eq(&'=').is_some();
+                Token::Operator(match (c, equal) {
+                    ('<', false) => Operator::Less,
+                    ('<', true) => Operator::LessOrEqual,
+                    ('>', false) => Operator::Greater,
+                    ('>', true) => Operator::GreaterOrEqual,
+                    ('=', _) => Operator::Equal,
+                    ('!', true) => Operator::NotEqual,
+                    _ => return Err(RuleError::UnexpectedToken(c.to_string())),
+                })
+            }
+            _ if c.is_alphanumeric() => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '.') {
+                    word.push(c);
+                }
+                Token::Word(word)
+            }
+            _ => return Err(RuleError::UnexpectedToken(c.to_string())),
+        };
+
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token, RuleError> {
+        let token = self.peek().cloned().ok_or(RuleError::UnexpectedEnd)?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn next_if_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expr(&mut self) -> Result<RuleExpr, RuleError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(RuleError::TooDeep);
+        }
+
+        let mut exprs = vec![self.and()?];
+        while self.next_if_keyword("or") {
+            exprs.push(self.and()?);
+        }
+
+        self.depth -= 1;
+        Ok(match exprs.len() {
+            1 => exprs.remove(0),
+            _ => RuleExpr::Or(exprs),
+        })
+    }
+
+    fn and(&mut self) -> Result<RuleExpr, RuleError> {
+        let mut exprs = vec![self.unary()?];
+        while self.next_if_keyword("and") {
+            exprs.push(self.unary()?);
+        }
+
+        Ok(match exprs.len() {
+            1 => exprs.remove(0),
+            _ => RuleExpr::And(exprs),
+        })
+    }
+
+    fn unary(&mut self) -> Result<RuleExpr, RuleError> {
+        if self.next_if_keyword("not") {
+            self.depth += 1;
+            if self.depth > MAX_DEPTH {
+                return Err(RuleError::TooDeep);
+            }
+            let expr = self.unary()?;
+            self.depth -= 1;
+            return Ok(RuleExpr::Not(Box::new(expr)));
+        }
+
+        if self.peek() == Some(&Token::Open) {
+            self.position += 1;
+            let expr = self.expr()?;
+            return match self.next()? {
+                Token::Close => Ok(expr),
+                token => Err(RuleError::UnexpectedToken(token.to_string())),
+            };
+        }
+
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<RuleExpr, RuleError> {
+        let mut words = Vec::new();
+        let operator = loop {
+            match self.next()? {
+                Token::Word(word) => words.push(word.to_lowercase()),
+                Token::Operator(operator) if !words.is_empty() => break operator,
+                token => return Err(RuleError::UnexpectedToken(token.to_string())),
+            }
+        };
+        let value = match self.next()? {
+            Token::Word(word) => word,
+            token => return Err(RuleError::UnexpectedToken(token.to_string())),
+        };
+
+        let subject = words.join(" ");
+        let invalid_value = || RuleError::InvalidValue(subject.clone(), value.clone());
+
+        if subject == "title" {
+            return match operator {
+                Operator::Equal | Operator::NotEqual => Ok(RuleExpr::Title(
+                    operator,
+                    value.parse().map_err(|_| invalid_value())?,
+                )),
+                _ => Err(RuleError::InvalidTitleOperator),
+            };
+        }
+
+        let parsed =
+            parse_subject(&words).ok_or_else(|| RuleError::UnknownSubject(subject.clone()))?;
+        let value: i64 = value.parse().map_err(|_| invalid_value())?;
+        if matches!(parsed, Subject::Rating { .. }) && i16::try_from(value).is_err() {
+            return Err(invalid_value());
+        }
+
+        Ok(RuleExpr::Compare(parsed, operator, value))
+    }
+}
+
+/// Reads the subject of a comparison, such as `peak blitz lichess` or `games rapid`
+fn parse_subject(words: &[String]) -> Option<Subject> {
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+    let provider = |word: Option<&&str>| match word {
+        Some(word) => word.parse::<Provider>().ok().map(Some),
+        None => Some(None),
+    };
+
+    match words[..] {
+        ["age"] => Some(Subject::Age),
+        ["games", ref rest @ ..] => {
+            let (format, rest) = match rest.split_first() {
+                Some((word, rest)) if word.parse::<Format>().is_ok() => (word.parse().ok(), rest),
+                _ => (None, rest),
+            };
+            if rest.len() > 1 {
+                return None;
+            }
+            Some(Subject::Games {
+                format,
+                provider: provider(rest.first())?,
+            })
+        }
+        _ => {
+            let (peak, rest) = match words.split_first() {
+                Some((&"peak", rest)) => (true, rest),
+                _ => (false, &words[..]),
+            };
+            let (target, rest) = rest.split_first()?;
+            if rest.len() > 1 {
+                return None;
+            }
+            Some(Subject::Rating {
+                target: target.parse().ok()?,
+                provider: provider(rest.first())?,
+                peak,
+            })
+        }
+    }
+}
+
+impl FromStr for RuleExpr {
+    type Err = RuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            position: 0,
+            depth: 0,
+        };
+
+        let expr = parser.expr()?;
+        match parser.peek() {
+            Some(token) => Err(RuleError::UnexpectedToken(token.to_string())),
+            None => Ok(expr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ratings::Rating;
+
+    fn profile() -> MemberProfile {
+        MemberProfile {
+            ratings: vec![
+                (
+                    Provider::Lichess,
+                    Format::Blitz,
+                    Rating::new(1600, None, 0, 80, false),
+                ),
+                (
+                    Provider::Chesscom,
+                    Format::Rapid,
+                    Rating::new(1550, None, 0, 40, false),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+            title: Some(Title::CM),
+            account_age: Some(400),
+            ..Default::default()
+        }
+    }
+
+    fn is_match(rule: &str) -> bool {
+        let formats = [Format::Blitz, Format::Rapid];
+        rule.parse::<RuleExpr>()
+            .unwrap()
+            .is_match(&profile(), &RatingPolicy::default(), &formats)
+    }
+
+    #[test]
+    fn parse_respects_precedence() {
+        let expr = "blitz >= 1500 or rapid >= 1500 and not games < 100"
+            .parse::<RuleExpr>()
+            .unwrap();
+
+        assert!(matches!(&expr, RuleExpr::Or(exprs) if matches!(exprs[1], RuleExpr::And(_))));
+        assert_eq!(
+            expr.to_string(),
+            "blitz >= 1500 or rapid >= 1500 and not games < 100"
+        );
+    }
+
+    #[test]
+    fn display_parses_back() {
+        for rule in [
+            "(blitz >= 1500 or rapid >= 1500) and games >= 100",
+            "not (title = GM or title = IM)",
+            "peak bullet lichess > 2000 and age >= 365",
+            "games rapid chesscom != 0",
+        ] {
+            let expr = rule.parse::<RuleExpr>().unwrap();
+            assert_eq!(expr.to_string(), rule);
+            assert_eq!(expr.to_string().parse::<RuleExpr>().unwrap(), expr);
+        }
+    }
+
+    #[test]
+    fn ratings_are_compared() {
+        assert!(is_match("blitz >= 1500 and rapid >= 1500 and games >= 100"));
+        assert!(!is_match("blitz >= 1500 and rapid >= 1500 and games > 120"));
+        assert!(is_match("blitz <= 1600 and blitz > 1599 and blitz = 1600"));
+        assert!(!is_match("blitz lichess < 1600 or rapid lichess >= 1000"));
+        assert!(is_match("blitz != 1500 and best >= 1600"));
+    }
+
+    #[test]
+    fn games_titles_and_age_are_compared() {
+        assert!(is_match("games blitz = 80 and games chesscom = 40"));
+        assert!(is_match("title = CM and title != GM"));
+        assert!(is_match("age >= 365 and not age > 400"));
+        assert!(!"age >= 1".parse::<RuleExpr>().unwrap().is_match(
+            &MemberProfile::default(),
+            &RatingPolicy::default(),
+            &[]
+        ));
+    }
+
+    #[test]
+    fn parse_reports_invalid_rules() {
+        assert_eq!(
+            "blitz >=".parse::<RuleExpr>(),
+            Err(RuleError::UnexpectedEnd)
+        );
+        assert_eq!(
+            "Club Player".parse::<RuleExpr>(),
+            Err(RuleError::UnexpectedEnd)
+        );
+        assert_eq!(
+            "elo > 1500".parse::<RuleExpr>(),
+            Err(RuleError::UnknownSubject("elo".to_string()))
+        );
+        assert_eq!(
+            "blitz > 40000".parse::<RuleExpr>(),
+            Err(RuleError::InvalidValue(
+                "blitz".to_string(),
+                "40000".to_string()
+            ))
+        );
+        assert_eq!(
+            "title > FM".parse::<RuleExpr>(),
+            Err(RuleError::InvalidTitleOperator)
+        );
+        assert_eq!(
+            "(blitz > 1500".parse::<RuleExpr>(),
+            Err(RuleError::UnexpectedEnd)
+        );
+        assert_eq!(
+            "blitz > 1500 rapid".parse::<RuleExpr>(),
+            Err(RuleError::UnexpectedToken("rapid".to_string()))
+        );
+        assert_eq!(
+            format!("{}blitz > 1500", "(".repeat(40)).parse::<RuleExpr>(),
+            Err(RuleError::TooDeep)
+        );
+    }
+}
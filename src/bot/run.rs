@@ -1,8 +1,9 @@
 use super::{
     commands::{account::*, meta::*},
     role_manager::RoleManager,
+    sync,
 };
-use crate::{bot::Handler, db::Pool, lichess};
+use crate::{bot::Handler, chesscom, config, db::Pool, lichess};
 use serenity::{
     client::bridge::gateway::{GatewayIntents, ShardManager},
     framework::{
@@ -39,6 +40,12 @@ impl TypeMapKey for LichessClientContainer {
     type Value = lichess::Client;
 }
 
+pub struct ChesscomClientContainer;
+
+impl TypeMapKey for ChesscomClientContainer {
+    type Value = chesscom::Client;
+}
+
 #[group]
 #[commands(help, account, rating, gdpr)]
 struct General;
@@ -56,7 +63,7 @@ async fn unknown_command(ctx: &Context, msg: &Message, unknown_command_name: &st
     }
 }
 
-pub async fn run(pool: &Pool, lichess: &lichess::Client) {
+pub async fn run(pool: &Pool, lichess: &lichess::Client, chesscom: &chesscom::Client) {
     trace!("run() called");
 
     // Configure the client with your Discord bot token in the environment.
@@ -114,6 +121,18 @@ pub async fn run(pool: &Pool, lichess: &lichess::Client) {
         data.insert::<PoolContainer>(pool.clone());
         data.insert::<RoleManagerContainer>(RoleManager::new());
         data.insert::<LichessClientContainer>(lichess.clone());
+        data.insert::<ChesscomClientContainer>(chesscom.clone());
+    }
+
+    match config::sync_interval() {
+        Some(interval_hours) => {
+            tokio::spawn(sync::run(
+                client.cache_and_http.http.clone(),
+                client.data.clone(),
+                interval_hours,
+            ));
+        }
+        None => info!("Background rating sync is disabled"),
     }
 
     let shard_manager = client.shard_manager.clone();
@@ -0,0 +1,155 @@
+use super::{
+    commands::rating_update::sync_member,
+    run::{ChesscomClientContainer, LichessClientContainer, PoolContainer, RoleManagerContainer},
+};
+use crate::{
+    lichess::{BulkProfiles, PrefetchedClient},
+    models::{Guild, User},
+    ratings::Provider,
+};
+use serenity::{http::Http, prelude::*};
+use std::{sync::Arc, time::Duration};
+use tokio::time::sleep;
+
+// Pause between two members, to stay well within the rate limits of lichess and chess.com
+const MEMBER_DELAY: Duration = Duration::from_secs(2);
+
+// Peak ratings and lichess teams rarely change, so syncs only fetch them again once a week. Peaks
+// are still raised to the current ratings in between.
+const SLOW_REFRESH: u64 = 7 * 24 * 3600;
+
+// Longest pause between two passes, so that members don't wait much longer than the interval
+const MAX_PASS_DELAY: Duration = Duration::from_secs(15 * 60);
+
+/// Refreshes the ratings and roles of every linked member once every `interval_hours`
+///
+/// The time of the last sync is saved with each member, so restarting the bot neither syncs
+/// everyone again nor delays the members that are due.
+pub async fn run(http: Arc<Http>, data: Arc<RwLock<TypeMap>>, interval_hours: u64) {
+    trace!("run() called");
+    let interval = interval_hours.saturating_mul(3600);
+    info!("Syncing ratings every {} hours", interval_hours);
+
+    loop {
+        // Waiting first also gives the guilds time to be loaded after a restart
+        sleep(Duration::from_secs(interval).min(MAX_PASS_DELAY)).await;
+        sync_all(&http, &data, interval).await;
+    }
+}
+
+/// Syncs the members of every guild who weren't synced for `interval` seconds
+async fn sync_all(http: &Http, data: &RwLock<TypeMap>, interval: u64) {
+    trace!("sync_all() called");
+    let pool;
+    let rm;
+    let lichess;
+    let chesscom;
+    {
+        let data = data.read().await;
+        pool = data.get::<PoolContainer>().unwrap().clone();
+        rm = data.get::<RoleManagerContainer>().unwrap().clone();
+        lichess = data.get::<LichessClientContainer>().unwrap().clone();
+        chesscom = data.get::<ChesscomClientContainer>().unwrap().clone();
+    }
+
+    for guild_id in rm.get_guild_ids() {
+        let guild = match Guild::find_or_create(&pool, guild_id, "").await {
+            Ok(guild) => guild,
+            Err(e) => {
+                error!("Unable to look up guild_id={}: {}", guild_id, e);
+                continue;
+            }
+        };
+
+        let users = match User::fetch_all(&pool, guild_id).await {
+            Ok(users) => users,
+            Err(e) => {
+                error!("Unable to fetch users of guild_id={}: {}", guild_id, e);
+                continue;
+            }
+        };
+
+        let users: Vec<User> = users
+            .into_iter()
+            .filter(|u| u.is_sync_due(interval))
+            .collect();
+
+        // Current lichess ratings of the whole guild only take a few requests
+        let usernames: Vec<&str> = users
+            .iter()
+            .flat_map(|u| u.get_accounts())
+            .filter(|a| a.provider() == Provider::Lichess)
+            .map(|a| a.username())
+            .collect();
+        let bulk = match lichess.fetch_user_profiles(&usernames).await {
+            Ok(bulk) => bulk,
+            Err(e) => {
+                warn!(
+                    "Could not fetch lichess profiles of guild_id={}: {}",
+                    guild_id, e
+                );
+                BulkProfiles::default()
+            }
+        };
+        if !bulk.missing.is_empty() || !bulk.closed.is_empty() {
+            info!(
+                "Lichess accounts missing={:?} and closed={:?} in guild_id={}",
+                bulk.missing, bulk.closed, guild_id
+            );
+        }
+        let prefetched = PrefetchedClient::new(&lichess, bulk);
+
+        for mut user in users {
+            let discord_id = user.discord_id();
+            debug!("Syncing discord_id={} in guild_id={}", discord_id, guild_id);
+
+            match sync_member(
+                http,
+                &pool,
+                &rm,
+                &[&prefetched, &chesscom],
+                &guild,
+                &mut user,
+                SLOW_REFRESH,
+            )
+            .await
+            {
+                Ok(changes) if changes.stripped && !changes.removed.is_empty() => info!(
+                    "Stripped role_ids={:?} from flagged discord_id={} in guild_id={}",
+                    changes.removed, discord_id, guild_id
+                ),
+                Ok(changes) if !changes.added.is_empty() || !changes.removed.is_empty() => info!(
+                    "Synced discord_id={} in guild_id={}, added role_ids={:?} and removed \
+                    role_ids={:?}",
+                    discord_id, guild_id, changes.added, changes.removed
+                ),
+                Ok(_) => {}
+                Err(e) => match e.retry_after() {
+                    Some(seconds) => {
+                        warn!(
+                            "Rate limited while syncing guild_id={}, resuming in {}s",
+                            guild_id, seconds
+                        );
+
+                        // The member is left due, and synced again at the next pass
+                        sleep(Duration::from_secs(seconds)).await;
+                    }
+                    None => {
+                        warn!(
+                            "Could not sync discord_id={} in guild_id={}: {}",
+                            discord_id, guild_id, e
+                        );
+
+                        // Failures, such as members who left the guild, are retried at the next
+                        // interval rather than at every pass
+                        if let Err(e) = user.mark_synced(&pool).await {
+                            error!("Unable to save discord_id={}: {}", discord_id, e);
+                        }
+                    }
+                },
+            }
+
+            sleep(MEMBER_DELAY).await;
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use super::{Error, Result};
+use crate::{
+    lichess::Format,
+    ratings::{self, PlayerProfile, Provider, Rating, RatingProvider},
+};
+use async_trait::async_trait;
+use reqwest::{header, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::collections::HashMap;
+
+const USER_AGENT: &str = concat!("liro/", env!("CARGO_PKG_VERSION"));
+
+// chess.com doesn't document how long to wait after a 429, so clients back off like for lichess
+const MIN_RATE_LIMIT_WAIT: u64 = 60;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChesscomUser {
+    username: String,
+    #[serde(default)]
+    location: Option<String>,
+    #[serde(default)]
+    bio: Option<String>,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    verified: bool,
+    /// Seconds since the Unix epoch
+    #[serde(default)]
+    joined: Option<u64>,
+}
+
+impl ChesscomUser {
+    pub fn get_username(&self) -> &str {
+        &self.username
+    }
+
+    /// Checks whether the user-editable profile fields contain `text`
+    pub fn profile_contains<T>(&self, text: T) -> bool
+    where
+        T: AsRef<str>,
+    {
+        [&self.location, &self.bio]
+            .iter()
+            .filter_map(|field| field.as_deref())
+            .any(|field| field.contains(text.as_ref()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+struct GameRating {
+    rating: i16,
+    rd: Option<i16>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+struct Record {
+    win: u32,
+    loss: u32,
+    draw: u32,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+struct BestRating {
+    rating: i16,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+struct FormatStats {
+    last: GameRating,
+    best: Option<BestRating>,
+    #[serde(default)]
+    record: Record,
+}
+
+impl FormatStats {
+    // chess.com neither tracks progress nor flags provisional ratings
+    fn to_rating(self) -> Rating {
+        let games = self.record.win + self.record.loss + self.record.draw;
+        Rating::new(self.last.rating, self.last.rd, 0, games, false)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Stats {
+    chess_blitz: Option<FormatStats>,
+    chess_bullet: Option<FormatStats>,
+    chess_rapid: Option<FormatStats>,
+    chess_daily: Option<FormatStats>,
+}
+
+impl Stats {
+    pub fn get_ratings(&self) -> HashMap<Format, Rating> {
+        trace!("Stats::get_ratings() called");
+        [
+            (Format::Blitz, self.chess_blitz),
+            (Format::Bullet, self.chess_bullet),
+            (Format::Rapid, self.chess_rapid),
+            (Format::Correspondence, self.chess_daily),
+        ]
+        .into_iter()
+        .filter_map(|(format, stats)| stats.map(|s| (format, s.to_rating())))
+        .collect()
+    }
+
+    pub fn get_peaks(&self) -> HashMap<Format, i16> {
+        trace!("Stats::get_peaks() called");
+        [
+            (Format::Blitz, self.chess_blitz),
+            (Format::Bullet, self.chess_bullet),
+            (Format::Rapid, self.chess_rapid),
+            (Format::Correspondence, self.chess_daily),
+        ]
+        .into_iter()
+        .filter_map(|(format, stats)| stats.and_then(|s| s.best).map(|b| (format, b.rating)))
+        .collect()
+    }
+}
+
+/// Error matching the `status` of a chess.com response, if it isn't successful
+fn status_error(status: StatusCode, retry_after: Option<&header::HeaderValue>) -> Option<Error> {
+    match status {
+        _ if status.is_success() => None,
+        StatusCode::NOT_FOUND => Some(Error::NotFound),
+        StatusCode::TOO_MANY_REQUESTS => Some(Error::RateLimited(
+            retry_after
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .unwrap_or_default()
+                .max(MIN_RATE_LIMIT_WAIT),
+        )),
+        _ => Some(Error::ServerError(status.as_u16())),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+}
+
+impl Client {
+    pub fn new() -> Client {
+        trace!("Client::new() called");
+
+        // chess.com's published data API is unauthenticated, but requests without a
+        // User-Agent are likely to get blocked.
+        let http = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .build()
+            .unwrap();
+
+        Client { http }
+    }
+
+    async fn fetch<T>(&self, url: String) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        trace!("Client::fetch() called");
+        let response = self.http.get(url).send().await?;
+
+        if let Some(error) = status_error(
+            response.status(),
+            response.headers().get(header::RETRY_AFTER),
+        ) {
+            return Err(error);
+        }
+
+        Ok(response.json::<T>().await?)
+    }
+
+    pub async fn fetch_user<U>(&self, username: U) -> Result<ChesscomUser>
+    where
+        U: AsRef<str>,
+    {
+        trace!("Client::fetch_user() called");
+        self.fetch(format!(
+            "https://api.chess.com/pub/player/{}",
+            username.as_ref().to_lowercase()
+        ))
+        .await
+    }
+
+    async fn fetch_stats<U>(&self, username: U) -> Result<Stats>
+    where
+        U: AsRef<str>,
+    {
+        trace!("Client::fetch_stats() called");
+        self.fetch(format!(
+            "https://api.chess.com/pub/player/{}/stats",
+            username.as_ref().to_lowercase()
+        ))
+        .await
+    }
+
+    /// Fetches the highest rating ever reached by the user in every format
+    pub async fn fetch_user_peaks<U>(&self, username: U) -> Result<HashMap<Format, i16>>
+    where
+        U: AsRef<str>,
+    {
+        trace!("Client::fetch_user_peaks() called");
+        Ok(self.fetch_stats(username).await?.get_peaks())
+    }
+}
+
+#[async_trait]
+impl RatingProvider for Client {
+    fn provider(&self) -> Provider {
+        Provider::Chesscom
+    }
+
+    async fn fetch_profile(&self, username: &str) -> ratings::Result<PlayerProfile> {
+        trace!("Client::fetch_profile() called");
+        let user = self.fetch_user(username).await?;
+        let stats = self.fetch_stats(username).await?;
+        Ok(PlayerProfile {
+            ratings: stats.get_ratings(),
+            // Peaks come with the current ratings, sparing a second request for the same stats
+            peaks: Some(stats.get_peaks()),
+            title: user.title.as_ref().and_then(|t| t.parse().ok()),
+            // chess.com has no equivalent to lichess patrons
+            patron: false,
+            verified: user.verified,
+            created_at: user.joined,
+            // Only lichess accounts are checked for closures and ToS violations
+            closed: false,
+            tos_violation: false,
+        })
+    }
+
+    async fn fetch_peaks(&self, username: &str) -> ratings::Result<HashMap<Format, i16>> {
+        trace!("Client::fetch_peaks() called");
+        Ok(self.fetch_user_peaks(username).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsuccessful_statuses_are_typed() {
+        let header = |v: &str| header::HeaderValue::from_str(v).unwrap();
+
+        assert!(status_error(StatusCode::OK, None).is_none());
+        assert!(matches!(
+            status_error(StatusCode::NOT_FOUND, None),
+            Some(Error::NotFound)
+        ));
+        assert!(matches!(
+            status_error(StatusCode::TOO_MANY_REQUESTS, None),
+            Some(Error::RateLimited(60))
+        ));
+        assert!(matches!(
+            status_error(StatusCode::TOO_MANY_REQUESTS, Some(&header("300"))),
+            Some(Error::RateLimited(300))
+        ));
+        assert!(matches!(
+            status_error(StatusCode::BAD_GATEWAY, None),
+            Some(Error::ServerError(502))
+        ));
+    }
+}
@@ -0,0 +1,17 @@
+use thiserror::Error;
+
+// `ServerError` is clearer than `Server` for errors on the side of chess.com
+#[allow(clippy::enum_variant_names)]
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("network error: {0}")]
+    Network(#[from] reqwest::Error),
+    #[error("chess.com user not found")]
+    NotFound,
+    #[error("rate limited by chess.com for {0} seconds")]
+    RateLimited(u64),
+    #[error("chess.com server error: HTTP {0}")]
+    ServerError(u16),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,7 @@
+mod client;
+mod error;
+
+pub use error::Error;
+use error::Result;
+
+pub use client::Client;
@@ -46,6 +46,24 @@ pub fn client_id() -> String {
     }
 }
 
+/// Hours between two background syncs of a member's ratings and roles, `None` if disabled
+pub fn sync_interval() -> Option<u64> {
+    trace!("sync_interval() called");
+    match env::var("SYNC_INTERVAL_HOURS").map(|v| v.parse::<u64>()) {
+        Ok(Ok(0)) => None,
+        Ok(Ok(v)) => Some(v),
+        Ok(Err(e)) => {
+            error!(
+                "Could not parse SYNC_INTERVAL_HOURS environment variable: {}",
+                e
+            );
+            warn!("Using default value 24 instead");
+            Some(24)
+        }
+        Err(_) => Some(24),
+    }
+}
+
 pub fn lichess_token() -> String {
     trace!("lichess_token() called");
     match env::var("LICHESS_API_TOKEN") {
@@ -120,4 +138,22 @@ mod tests {
         env::remove_var("CLIENT_ID");
         assert_eq!(client_id(), "liro-test-bot");
     }
+
+    #[serial]
+    #[test]
+    fn sync_interval_reads_env_var() {
+        env::set_var("SYNC_INTERVAL_HOURS", "6");
+        assert_eq!(sync_interval(), Some(6));
+        env::set_var("SYNC_INTERVAL_HOURS", "0");
+        assert_eq!(sync_interval(), None);
+    }
+
+    #[serial]
+    #[test]
+    fn sync_interval_uses_default_value() {
+        env::remove_var("SYNC_INTERVAL_HOURS");
+        assert_eq!(sync_interval(), Some(24));
+        env::set_var("SYNC_INTERVAL_HOURS", "daily");
+        assert_eq!(sync_interval(), Some(24));
+    }
 }
@@ -2,10 +2,12 @@
 extern crate log;
 
 mod bot;
+mod chesscom;
 mod config;
 mod db;
 mod lichess;
 mod models;
+mod ratings;
 mod run;
 mod web;
 
@@ -1,8 +1,23 @@
-use super::{Format, Result};
-use crate::config;
-use reqwest::header;
-use serde::Deserialize;
-use std::collections::HashMap;
+use super::{Error, Format, Result};
+use crate::{
+    config,
+    ratings::{self, PlayerProfile, Provider, Rating, RatingProvider},
+};
+use async_trait::async_trait;
+use reqwest::{header, RequestBuilder, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use strum::IntoEnumIterator;
+
+// Lichess asks clients to wait a full minute after being rate limited
+const MIN_RATE_LIMIT_WAIT: u64 = 60;
+
+// Most users the bulk endpoint of lichess accepts in a single request
+const MAX_BULK_USERS: usize = 300;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct LichessUser {
@@ -28,31 +43,188 @@ struct AccessToken {
 #[derive(Debug, Clone, Copy, Deserialize)]
 struct FormatRating {
     rating: Option<i16>,
+    rd: Option<i16>,
+    prog: Option<i16>,
+    games: Option<u32>,
     prov: Option<bool>,
 }
 
+impl FormatRating {
+    fn to_rating(self) -> Option<Rating> {
+        self.rating.map(|rating| {
+            Rating::new(
+                rating,
+                self.rd,
+                self.prog.unwrap_or_default(),
+                self.games.unwrap_or_default(),
+                self.prov.unwrap_or_default(),
+            )
+        })
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct Profile {
+    /// Lowercase username
+    #[serde(default)]
+    id: String,
+    // Closed accounts come without ratings
+    #[serde(default)]
     perfs: HashMap<String, FormatRating>,
+    title: Option<String>,
+    #[serde(default)]
+    patron: bool,
+    #[serde(default)]
+    verified: bool,
+    /// Milliseconds since the Unix epoch
+    #[serde(default)]
+    created_at: Option<u64>,
+    #[serde(default)]
+    disabled: bool,
+    #[serde(default)]
+    tos_violation: bool,
 }
 
 impl Profile {
-    pub fn get_ratings(&self) -> HashMap<Format, i16> {
+    pub fn get_ratings(&self) -> HashMap<Format, Rating> {
         trace!("Profile::get_ratings_for() called");
         self.perfs
             .iter()
-            .filter_map(|(k, v)| match (v.prov, v.rating, k.parse::<Format>()) {
-                (None, Some(rating), Ok(format)) => Some((format, rating)),
+            .filter_map(|(k, v)| match (v.to_rating(), k.parse::<Format>()) {
+                (Some(rating), Ok(format)) => Some((format, rating)),
                 _ => None,
             })
             .collect()
     }
+
+    fn to_player_profile(&self) -> PlayerProfile {
+        trace!("Profile::to_player_profile() called");
+        PlayerProfile {
+            ratings: self.get_ratings(),
+            // Lichess only publishes peaks in the rating history
+            peaks: None,
+            title: self.title.as_ref().and_then(|t| t.parse().ok()),
+            patron: self.patron,
+            verified: self.verified,
+            created_at: self.created_at.map(|t| t / 1000),
+            closed: self.disabled,
+            tos_violation: self.tos_violation,
+        }
+    }
+}
+
+/// Profiles retrieved in bulk by [`Client::fetch_user_profiles`]
+#[derive(Debug, Clone, Default)]
+pub struct BulkProfiles {
+    /// Profiles of the accounts lichess knows about, by lowercase username
+    pub profiles: HashMap<String, PlayerProfile>,
+    /// Usernames lichess doesn't know about
+    pub missing: Vec<String>,
+    /// Usernames of closed accounts
+    pub closed: Vec<String>,
+}
+
+impl BulkProfiles {
+    /// Sorts the `profiles` returned for `usernames` into open, closed and missing accounts
+    fn sort<U>(usernames: &[U], profiles: Vec<Profile>) -> Self
+    where
+        U: AsRef<str>,
+    {
+        trace!("BulkProfiles::sort() called");
+        let mut bulk = BulkProfiles::default();
+
+        for profile in profiles {
+            let id = profile.id.to_lowercase();
+            if profile.disabled {
+                bulk.closed.push(id.clone());
+            }
+            bulk.profiles.insert(id, profile.to_player_profile());
+        }
+
+        // Lichess leaves unknown users out of its answer
+        bulk.missing = usernames
+            .iter()
+            .map(|u| u.as_ref().to_lowercase())
+            .filter(|u| !bulk.profiles.contains_key(u))
+            .collect();
+
+        bulk
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Team {
+    id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RatingHistory {
+    name: String,
+    // Each point is [year, month, day, rating]
+    points: Vec<[i16; 4]>,
+}
+
+impl RatingHistory {
+    fn get_format(&self) -> Option<Format> {
+        Format::iter().find(|f| f.to_string() == self.name)
+    }
+
+    fn get_peak(&self) -> Option<i16> {
+        self.points.iter().map(|p| p[3]).max()
+    }
+}
+
+/// Keeps the requests of every clone of a client within the rate limits of lichess
+#[derive(Debug, Clone, Default)]
+struct RateLimiter {
+    /// Held while a request is being sent, as lichess asks for one request at a time
+    turn: Arc<tokio::sync::Mutex<()>>,
+    blocked_until: Arc<Mutex<Option<Instant>>>,
+}
+
+impl RateLimiter {
+    /// Seconds left before requests can be sent again, if lichess rate limited the client
+    fn remaining(&self) -> Option<u64> {
+        let blocked_until = (*self.blocked_until.lock().unwrap())?;
+        let remaining = blocked_until.checked_duration_since(Instant::now())?;
+
+        // Rounded up, so that callers never retry too early
+        Some(remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0))
+    }
+
+    fn block(&self, seconds: u64) {
+        *self.blocked_until.lock().unwrap() = Some(Instant::now() + Duration::from_secs(seconds));
+    }
+}
+
+/// Seconds to wait after a 429, following `Retry-After` when lichess sends a longer delay
+fn rate_limit_wait(retry_after: Option<&header::HeaderValue>) -> u64 {
+    retry_after
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or_default()
+        .max(MIN_RATE_LIMIT_WAIT)
+}
+
+/// Error matching the `status` of a lichess response, if it isn't successful
+fn status_error(status: StatusCode, retry_after: Option<&header::HeaderValue>) -> Option<Error> {
+    match status {
+        _ if status.is_success() => None,
+        StatusCode::NOT_FOUND => Some(Error::NotFound),
+        // The token endpoint answers 400 to invalid or expired authorization codes
+        StatusCode::BAD_REQUEST | StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            Some(Error::Unauthorized)
+        }
+        StatusCode::TOO_MANY_REQUESTS => Some(Error::RateLimited(rate_limit_wait(retry_after))),
+        _ => Some(Error::ServerError(status.as_u16())),
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Client {
     http: reqwest::Client,
+    limiter: RateLimiter,
 }
 
 impl Client {
@@ -75,7 +247,43 @@ impl Client {
             .build()
             .unwrap();
 
-        Client { http }
+        Client {
+            http,
+            limiter: Default::default(),
+        }
+    }
+
+    /// Sends `request` once lichess accepts requests again, and decodes its JSON response
+    ///
+    /// Fails with [`Error::RateLimited`] while the client is rate limited, rather than making
+    /// callers wait for up to a minute.
+    async fn fetch<T>(&self, request: RequestBuilder) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        trace!("Client::fetch() called");
+        let response = {
+            let _turn = self.limiter.turn.lock().await;
+
+            if let Some(seconds) = self.limiter.remaining() {
+                return Err(Error::RateLimited(seconds));
+            }
+
+            request.send().await?
+        };
+
+        if let Some(error) = status_error(
+            response.status(),
+            response.headers().get(header::RETRY_AFTER),
+        ) {
+            if let Error::RateLimited(seconds) = error {
+                warn!("Rate limited by lichess, pausing requests for {}s", seconds);
+                self.limiter.block(seconds);
+            }
+            return Err(error);
+        }
+
+        Ok(serde_json::from_str(&response.text().await?)?)
     }
 
     pub async fn validate_token<T>(&self, access_token: T) -> Result<LichessUser>
@@ -84,24 +292,87 @@ impl Client {
     {
         trace!("Client::validate_token() called");
 
-        let result = self
-            .http
-            .get("https://lichess.org/api/account")
-            .header("Authorization", format!("Bearer {}", access_token.as_ref()))
-            .send()
-            .await?;
-
-        Ok(result.json::<LichessUser>().await?)
+        self.fetch(
+            self.http
+                .get("https://lichess.org/api/account")
+                .header("Authorization", format!("Bearer {}", access_token.as_ref())),
+        )
+        .await
     }
 
-    pub async fn fetch_user_ratings<U>(&self, username: U) -> Result<HashMap<Format, i16>>
+    /// Fetches the ratings, title and flags of the user
+    pub async fn fetch_user_profile<U>(&self, username: U) -> Result<PlayerProfile>
     where
         U: AsRef<str>,
     {
-        trace!("Client::fetch_user_ratings() called");
+        trace!("Client::fetch_user_profile() called");
         let url = format!("https://lichess.org/api/user/{}", username.as_ref());
-        let profile = self.http.get(url).send().await?.json::<Profile>().await?;
-        Ok(profile.get_ratings())
+        let profile: Profile = self.fetch(self.http.get(url)).await?;
+
+        Ok(profile.to_player_profile())
+    }
+
+    /// Fetches the ratings, title and flags of many users at once
+    ///
+    /// Users are fetched by batches of 300, so a whole guild only takes a few requests.
+    pub async fn fetch_user_profiles<U>(&self, usernames: &[U]) -> Result<BulkProfiles>
+    where
+        U: AsRef<str>,
+    {
+        trace!("Client::fetch_user_profiles() called");
+        let mut profiles = Vec::with_capacity(usernames.len());
+
+        for batch in usernames.chunks(MAX_BULK_USERS) {
+            let body = batch
+                .iter()
+                .map(|u| u.as_ref())
+                .collect::<Vec<_>>()
+                .join(",");
+            profiles.extend(
+                self.fetch::<Vec<Profile>>(
+                    self.http
+                        .post("https://lichess.org/api/users")
+                        .header(header::CONTENT_TYPE, "text/plain")
+                        .body(body),
+                )
+                .await?,
+            );
+        }
+
+        Ok(BulkProfiles::sort(usernames, profiles))
+    }
+
+    /// Fetches the highest rating ever reached by the user in every format
+    pub async fn fetch_user_peaks<U>(&self, username: U) -> Result<HashMap<Format, i16>>
+    where
+        U: AsRef<str>,
+    {
+        trace!("Client::fetch_user_peaks() called");
+        let url = format!(
+            "https://lichess.org/api/user/{}/rating-history",
+            username.as_ref()
+        );
+        let history: Vec<RatingHistory> = self.fetch(self.http.get(url)).await?;
+
+        Ok(history
+            .iter()
+            .filter_map(|h| match (h.get_format(), h.get_peak()) {
+                (Some(format), Some(peak)) => Some((format, peak)),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Fetches the IDs of the teams the user is a member of
+    pub async fn fetch_user_teams<U>(&self, username: U) -> Result<Vec<String>>
+    where
+        U: AsRef<str>,
+    {
+        trace!("Client::fetch_user_teams() called");
+        let url = format!("https://lichess.org/api/team/of/{}", username.as_ref());
+        let teams: Vec<Team> = self.fetch(self.http.get(url)).await?;
+
+        Ok(teams.into_iter().map(|t| t.id).collect())
     }
 
     pub async fn fetch_access_token<C, V>(&self, code: C, code_verifier: V) -> Result<String>
@@ -121,15 +392,150 @@ impl Client {
             ),
         ];
 
-        let parsed = self
-            .http
-            .post("https://lichess.org/api/token")
-            .form(&query_params)
-            .send()
-            .await?
-            .json::<AccessToken>()
+        let parsed: AccessToken = self
+            .fetch(
+                self.http
+                    .post("https://lichess.org/api/token")
+                    .form(&query_params),
+            )
             .await?;
 
         Ok(parsed.access_token)
     }
 }
+
+#[async_trait]
+impl RatingProvider for Client {
+    fn provider(&self) -> Provider {
+        Provider::Lichess
+    }
+
+    async fn fetch_profile(&self, username: &str) -> ratings::Result<PlayerProfile> {
+        trace!("Client::fetch_profile() called");
+        Ok(self.fetch_user_profile(username).await?)
+    }
+
+    async fn fetch_peaks(&self, username: &str) -> ratings::Result<HashMap<Format, i16>> {
+        trace!("Client::fetch_peaks() called");
+        Ok(self.fetch_user_peaks(username).await?)
+    }
+
+    async fn fetch_teams(&self, username: &str) -> ratings::Result<Vec<String>> {
+        trace!("Client::fetch_teams() called");
+        Ok(self.fetch_user_teams(username).await?)
+    }
+}
+
+/// Lichess client answering profile requests from profiles fetched in bulk beforehand
+///
+/// Users missing from the bulk profiles are fetched one by one, like [`Client`] does.
+pub struct PrefetchedClient<'a> {
+    client: &'a Client,
+    profiles: HashMap<String, PlayerProfile>,
+}
+
+impl<'a> PrefetchedClient<'a> {
+    pub fn new(client: &'a Client, bulk: BulkProfiles) -> Self {
+        trace!("PrefetchedClient::new() called");
+        PrefetchedClient {
+            client,
+            profiles: bulk.profiles,
+        }
+    }
+}
+
+#[async_trait]
+impl RatingProvider for PrefetchedClient<'_> {
+    fn provider(&self) -> Provider {
+        Provider::Lichess
+    }
+
+    async fn fetch_profile(&self, username: &str) -> ratings::Result<PlayerProfile> {
+        trace!("PrefetchedClient::fetch_profile() called");
+        match self.profiles.get(&username.to_lowercase()) {
+            Some(profile) => Ok(profile.clone()),
+            None => self.client.fetch_profile(username).await,
+        }
+    }
+
+    async fn fetch_peaks(&self, username: &str) -> ratings::Result<HashMap<Format, i16>> {
+        trace!("PrefetchedClient::fetch_peaks() called");
+        self.client.fetch_peaks(username).await
+    }
+
+    async fn fetch_teams(&self, username: &str) -> ratings::Result<Vec<String>> {
+        trace!("PrefetchedClient::fetch_teams() called");
+        self.client.fetch_teams(username).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bulk_profiles_are_sorted_into_open_closed_and_missing_accounts() {
+        let profiles: Vec<Profile> = serde_json::from_str(
+            r#"[
+                {"id":"drnykterstein","username":"DrNykterstein","title":"GM",
+                 "perfs":{"blitz":{"games":100,"rating":3000,"rd":45,"prog":12}}},
+                {"id":"closed","username":"Closed","disabled":true},
+                {"id":"cheater","username":"Cheater","tosViolation":true,
+                 "perfs":{"blitz":{"games":10,"rating":2500,"rd":60,"prog":0}}}
+            ]"#,
+        )
+        .unwrap();
+
+        let bulk = BulkProfiles::sort(&["DrNykterstein", "closed", "cheater", "Nobody"], profiles);
+
+        assert_eq!(
+            bulk.profiles["drnykterstein"].ratings[&Format::Blitz].rating(),
+            3000
+        );
+        assert!(!bulk.profiles["drnykterstein"].tos_violation);
+        assert!(bulk.profiles["closed"].closed);
+        assert!(bulk.profiles["cheater"].tos_violation);
+        assert_eq!(bulk.closed, vec!["closed".to_string()]);
+        assert_eq!(bulk.missing, vec!["nobody".to_string()]);
+    }
+
+    #[test]
+    fn rate_limits_last_at_least_a_minute() {
+        let header = |v: &str| header::HeaderValue::from_str(v).unwrap();
+
+        assert_eq!(rate_limit_wait(None), 60);
+        assert_eq!(rate_limit_wait(Some(&header("5"))), 60);
+        assert_eq!(rate_limit_wait(Some(&header("120"))), 120);
+        assert_eq!(rate_limit_wait(Some(&header("soon"))), 60);
+    }
+
+    #[test]
+    fn unsuccessful_statuses_are_typed() {
+        assert!(status_error(StatusCode::OK, None).is_none());
+        assert!(matches!(
+            status_error(StatusCode::NOT_FOUND, None),
+            Some(Error::NotFound)
+        ));
+        assert!(matches!(
+            status_error(StatusCode::UNAUTHORIZED, None),
+            Some(Error::Unauthorized)
+        ));
+        assert!(matches!(
+            status_error(StatusCode::TOO_MANY_REQUESTS, None),
+            Some(Error::RateLimited(60))
+        ));
+        assert!(matches!(
+            status_error(StatusCode::BAD_GATEWAY, None),
+            Some(Error::ServerError(502))
+        ));
+    }
+
+    #[test]
+    fn rate_limiter_is_shared_between_clones() {
+        let limiter = RateLimiter::default();
+        assert_eq!(limiter.remaining(), None);
+
+        limiter.clone().block(60);
+        assert_eq!(limiter.remaining(), Some(60));
+    }
+}
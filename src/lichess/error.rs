@@ -1,11 +1,21 @@
 use thiserror::Error;
 
+// `ServerError` is clearer than `Server` for errors on the side of lichess
+#[allow(clippy::enum_variant_names)]
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("network error: {0}")]
     Network(#[from] reqwest::Error),
-    #[error("invalid authentication error: {0}")]
-    InvalidAuthentication(#[from] serde_json::Error),
+    #[error("lichess user not found")]
+    NotFound,
+    #[error("lichess refused the credentials")]
+    Unauthorized,
+    #[error("rate limited by lichess for {0} more seconds")]
+    RateLimited(u64),
+    #[error("lichess server error: HTTP {0}")]
+    ServerError(u16),
+    #[error("invalid response from lichess: {0}")]
+    Decode(#[from] serde_json::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
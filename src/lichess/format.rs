@@ -3,23 +3,69 @@ use std::{fmt::Display, str::FromStr};
 use strum_macros::EnumIter;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
+#[serde(rename_all = "camelCase")]
 pub enum Format {
+    Antichess,
+    Atomic,
     Blitz,
     Bullet,
+    Chess960,
     Classical,
+    Correspondence,
+    Crazyhouse,
+    Horde,
+    KingOfTheHill,
+    Puzzle,
+    RacingKings,
     Rapid,
+    ThreeCheck,
+    UltraBullet,
+}
+
+impl Format {
+    /// Single lowercase word identifying the format in role names
+    pub fn key(&self) -> &'static str {
+        match self {
+            Format::Antichess => "antichess",
+            Format::Atomic => "atomic",
+            Format::Blitz => "blitz",
+            Format::Bullet => "bullet",
+            Format::Chess960 => "chess960",
+            Format::Classical => "classical",
+            Format::Correspondence => "correspondence",
+            Format::Crazyhouse => "crazyhouse",
+            Format::Horde => "horde",
+            Format::KingOfTheHill => "kingofthehill",
+            Format::Puzzle => "puzzle",
+            Format::RacingKings => "racingkings",
+            Format::Rapid => "rapid",
+            Format::ThreeCheck => "threecheck",
+            Format::UltraBullet => "ultrabullet",
+        }
+    }
 }
 
 impl FromStr for Format {
     type Err = ();
 
+    // Accepts both the role name keys and the perf names used by the lichess API
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
+            "antichess" => Ok(Format::Antichess),
+            "atomic" => Ok(Format::Atomic),
             "blitz" => Ok(Format::Blitz),
             "bullet" => Ok(Format::Bullet),
-            "classical" => Ok(Format::Classical),
+            "chess960" | "960" => Ok(Format::Chess960),
+            "classical" | "classic" | "standard" => Ok(Format::Classical),
+            "correspondence" | "corr" | "daily" => Ok(Format::Correspondence),
+            "crazyhouse" => Ok(Format::Crazyhouse),
+            "horde" => Ok(Format::Horde),
+            "kingofthehill" | "kingOfTheHill" | "koth" => Ok(Format::KingOfTheHill),
+            "puzzle" | "puzzles" => Ok(Format::Puzzle),
+            "racingkings" | "racingKings" => Ok(Format::RacingKings),
             "rapid" => Ok(Format::Rapid),
+            "threecheck" | "threeCheck" | "3check" => Ok(Format::ThreeCheck),
+            "ultrabullet" | "ultraBullet" => Ok(Format::UltraBullet),
             _ => Err(()),
         }
     }
@@ -28,10 +74,21 @@ impl FromStr for Format {
 impl Display for Format {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let s = match self {
+            Format::Antichess => "Antichess",
+            Format::Atomic => "Atomic",
             Format::Blitz => "Blitz",
             Format::Bullet => "Bullet",
+            Format::Chess960 => "Chess960",
             Format::Classical => "Classical",
+            Format::Correspondence => "Correspondence",
+            Format::Crazyhouse => "Crazyhouse",
+            Format::Horde => "Horde",
+            Format::KingOfTheHill => "King of the Hill",
+            Format::Puzzle => "Puzzles",
+            Format::RacingKings => "Racing Kings",
             Format::Rapid => "Rapid",
+            Format::ThreeCheck => "Three-check",
+            Format::UltraBullet => "UltraBullet",
         };
         write!(f, "{}", s)
     }
@@ -40,6 +97,7 @@ impl Display for Format {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use strum::IntoEnumIterator;
 
     #[test]
     fn format_can_be_compared() {
@@ -54,10 +112,57 @@ mod tests {
         assert_eq!("blitz".parse::<Format>().unwrap(), Format::Blitz);
         assert_eq!("bullet".parse::<Format>().unwrap(), Format::Bullet);
         assert_eq!("rapid".parse::<Format>().unwrap(), Format::Rapid);
+        assert_eq!(
+            "correspondence".parse::<Format>().unwrap(),
+            Format::Correspondence
+        );
+    }
+
+    #[test]
+    fn format_parses_chesscom_daily_as_correspondence() {
+        assert_eq!("daily".parse::<Format>().unwrap(), Format::Correspondence);
     }
 
     #[test]
     fn format_parser_reports_error() {
         assert!("foo".parse::<Format>().is_err());
     }
+
+    #[test]
+    fn format_parses_lichess_perf_names() {
+        assert_eq!(
+            "ultraBullet".parse::<Format>().unwrap(),
+            Format::UltraBullet
+        );
+        assert_eq!(
+            "kingOfTheHill".parse::<Format>().unwrap(),
+            Format::KingOfTheHill
+        );
+        assert_eq!(
+            "racingKings".parse::<Format>().unwrap(),
+            Format::RacingKings
+        );
+        assert_eq!("threeCheck".parse::<Format>().unwrap(), Format::ThreeCheck);
+        assert_eq!("chess960".parse::<Format>().unwrap(), Format::Chess960);
+        assert_eq!("puzzle".parse::<Format>().unwrap(), Format::Puzzle);
+    }
+
+    #[test]
+    fn format_key_can_be_parsed_back() {
+        for format in Format::iter() {
+            assert_eq!(format.key().parse::<Format>().unwrap(), format);
+        }
+    }
+
+    #[test]
+    fn format_serializes_to_lichess_perf_names() {
+        assert_eq!(
+            serde_json::to_string(&Format::KingOfTheHill).unwrap(),
+            "\"kingOfTheHill\""
+        );
+        assert_eq!(
+            serde_json::from_str::<Format>("\"blitz\"").unwrap(),
+            Format::Blitz
+        );
+    }
 }
@@ -6,5 +6,5 @@ mod format;
 pub use error::Error;
 use error::Result;
 
-pub use client::Client;
+pub use client::{BulkProfiles, Client, PrefetchedClient};
 pub use format::Format;
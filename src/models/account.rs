@@ -0,0 +1,279 @@
+use super::{now, Result};
+use crate::{
+    lichess::Format,
+    ratings::{Provider, Rating, RatingProvider, Title},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt};
+
+/// Account from a rating provider linked to a Discord user
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Account {
+    provider: Provider,
+    username: String,
+    #[serde(default)]
+    primary: bool,
+    #[serde(default)]
+    ratings: HashMap<Format, Rating>,
+    #[serde(default)]
+    peaks: HashMap<Format, i16>,
+    #[serde(default)]
+    title: Option<Title>,
+    #[serde(default)]
+    patron: bool,
+    #[serde(default)]
+    verified: bool,
+    #[serde(default)]
+    teams: Vec<String>,
+    #[serde(default)]
+    created_at: Option<u64>,
+    #[serde(default)]
+    closed: bool,
+    #[serde(default)]
+    tos_violation: bool,
+    /// When the peaks were last fetched, in seconds since the Unix epoch
+    #[serde(default)]
+    peaks_updated_at: Option<u64>,
+    /// When the teams were last fetched, in seconds since the Unix epoch
+    #[serde(default)]
+    teams_updated_at: Option<u64>,
+}
+
+/// Checks whether data fetched at `updated_at` is at least `max_age` seconds old at `now`
+fn is_stale(updated_at: Option<u64>, max_age: u64, now: u64) -> bool {
+    match updated_at {
+        Some(updated_at) => now.saturating_sub(updated_at) >= max_age,
+        None => true,
+    }
+}
+
+impl Account {
+    pub fn new<U>(provider: Provider, username: U) -> Self
+    where
+        U: Into<String>,
+    {
+        trace!("Account::new() called");
+        Account {
+            provider,
+            username: username.into(),
+            primary: false,
+            ratings: Default::default(),
+            peaks: Default::default(),
+            title: None,
+            patron: false,
+            verified: false,
+            teams: vec![],
+            created_at: None,
+            closed: false,
+            tos_violation: false,
+            peaks_updated_at: None,
+            teams_updated_at: None,
+        }
+    }
+
+    pub fn provider(&self) -> Provider {
+        trace!("Account::provider() called");
+        self.provider
+    }
+
+    pub fn username(&self) -> &str {
+        trace!("Account::username() called");
+        &self.username
+    }
+
+    pub fn is_primary(&self) -> bool {
+        trace!("Account::is_primary() called");
+        self.primary
+    }
+
+    pub(super) fn set_primary(&mut self, primary: bool) {
+        trace!("Account::set_primary() called");
+        self.primary = primary;
+    }
+
+    /// Checks whether the account is the one designated by `name`
+    ///
+    /// `name` is either a username, or a username prefixed by its provider such as
+    /// `chesscom:username`.
+    pub fn is_named(&self, name: &str) -> bool {
+        trace!("Account::is_named() called");
+        match name.split_once(':') {
+            Some((provider, username)) => {
+                matches!(provider.parse::<Provider>(), Ok(p) if p == self.provider)
+                    && self.username.eq_ignore_ascii_case(username)
+            }
+            None => self.username.eq_ignore_ascii_case(name),
+        }
+    }
+
+    pub fn get_ratings(&self) -> &HashMap<Format, Rating> {
+        trace!("Account::get_ratings() called");
+        &self.ratings
+    }
+
+    pub(super) fn set_ratings(&mut self, ratings: HashMap<Format, Rating>) {
+        trace!("Account::set_ratings() called");
+        self.ratings = ratings;
+    }
+
+    /// Highest ratings ever reached, per format
+    pub fn get_peaks(&self) -> &HashMap<Format, i16> {
+        trace!("Account::get_peaks() called");
+        &self.peaks
+    }
+
+    pub(super) fn set_peaks(&mut self, peaks: HashMap<Format, i16>) {
+        trace!("Account::set_peaks() called");
+        self.peaks = peaks;
+    }
+
+    pub fn get_title(&self) -> Option<Title> {
+        trace!("Account::get_title() called");
+        self.title
+    }
+
+    pub fn is_patron(&self) -> bool {
+        trace!("Account::is_patron() called");
+        self.patron
+    }
+
+    pub fn is_verified(&self) -> bool {
+        trace!("Account::is_verified() called");
+        self.verified
+    }
+
+    /// IDs of the lichess teams the account was a member of when last checked
+    pub fn get_teams(&self) -> &[String] {
+        trace!("Account::get_teams() called");
+        &self.teams
+    }
+
+    /// When the account was created, in seconds since the Unix epoch
+    pub fn get_created_at(&self) -> Option<u64> {
+        trace!("Account::get_created_at() called");
+        self.created_at
+    }
+
+    /// Whether the account was closed or marked for violating the terms of service
+    pub fn is_flagged(&self) -> bool {
+        trace!("Account::is_flagged() called");
+        self.closed || self.tos_violation
+    }
+
+    /// Raises the peaks of the account to its current ratings, where they are higher
+    fn raise_peaks(&mut self) {
+        for (format, rating) in &self.ratings {
+            let peak = self.peaks.entry(*format).or_insert(rating.rating());
+            *peak = (*peak).max(rating.rating());
+        }
+    }
+
+    /// Refreshes the ratings, peak ratings, title and flags of the account from `provider`
+    ///
+    /// Peaks fetched less than `max_age` seconds ago aren't fetched again, they are only raised
+    /// to the current ratings. This spares bulk syncs a request per account.
+    pub async fn update(&mut self, provider: &dyn RatingProvider, max_age: u64) -> Result<()> {
+        trace!("Account::update() called");
+        let profile = provider.fetch_profile(&self.username).await?;
+        self.closed = profile.closed;
+        self.tos_violation = profile.tos_violation;
+
+        // Closed accounts come without ratings, the last known ones are kept for the guild
+        // policy on flagged accounts to decide on
+        if profile.closed {
+            return Ok(());
+        }
+
+        self.ratings = profile.ratings;
+        self.title = profile.title;
+        self.patron = profile.patron;
+        self.verified = profile.verified;
+        self.created_at = profile.created_at.or(self.created_at);
+
+        let now = now();
+        match profile.peaks {
+            Some(peaks) => {
+                self.peaks = peaks;
+                self.peaks_updated_at = Some(now);
+            }
+            None if is_stale(self.peaks_updated_at, max_age, now) => {
+                self.peaks = provider.fetch_peaks(&self.username).await?;
+                self.peaks_updated_at = Some(now);
+            }
+            None => self.raise_peaks(),
+        }
+
+        Ok(())
+    }
+
+    /// Refreshes the teams of the account from `provider`, unless they were fetched less than
+    /// `max_age` seconds ago
+    pub async fn update_teams(
+        &mut self,
+        provider: &dyn RatingProvider,
+        max_age: u64,
+    ) -> Result<()> {
+        trace!("Account::update_teams() called");
+        let now = now();
+        if !self.closed && is_stale(self.teams_updated_at, max_age, now) {
+            self.teams = provider.fetch_teams(&self.username).await?;
+            self.teams_updated_at = Some(now);
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        trace!("Account::fmt() called");
+        write!(f, "{} on {}", self.username, self.provider.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_named_matches_usernames_regardless_of_case() {
+        let account = Account::new(Provider::Lichess, "DrNykterstein");
+
+        assert!(account.is_named("drnykterstein"));
+        assert!(!account.is_named("Hikaru"));
+    }
+
+    #[test]
+    fn is_named_can_be_restricted_to_a_provider() {
+        let account = Account::new(Provider::Chesscom, "Hikaru");
+
+        assert!(account.is_named("chesscom:hikaru"));
+        assert!(account.is_named("chess.com:Hikaru"));
+        assert!(!account.is_named("lichess:Hikaru"));
+    }
+
+    #[test]
+    fn data_goes_stale_after_max_age() {
+        assert!(is_stale(None, 3600, 100));
+        assert!(!is_stale(Some(100), 3600, 3699));
+        assert!(is_stale(Some(100), 3600, 3700));
+        assert!(is_stale(Some(100), 0, 100));
+    }
+
+    #[test]
+    fn peaks_are_raised_to_current_ratings() {
+        let mut account = Account::new(Provider::Lichess, "DrNykterstein");
+        account.peaks = HashMap::from([(Format::Blitz, 3000), (Format::Bullet, 3200)]);
+        account.ratings = HashMap::from([
+            (Format::Blitz, Rating::new(3100, None, 0, 10, false)),
+            (Format::Bullet, Rating::new(3100, None, 0, 10, false)),
+            (Format::Rapid, Rating::new(2800, None, 0, 10, false)),
+        ]);
+
+        account.raise_peaks();
+
+        assert_eq!(account.peaks[&Format::Blitz], 3100);
+        assert_eq!(account.peaks[&Format::Bullet], 3200);
+        assert_eq!(account.peaks[&Format::Rapid], 2800);
+    }
+}
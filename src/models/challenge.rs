@@ -11,6 +11,8 @@ pub struct Challenge {
     guild_id: u64,
     discord_id: u64,
     code_verifier: Vec<u8>,
+    #[serde(default)]
+    chesscom_username: Option<String>,
 }
 
 fn key(id: u64) -> String {
@@ -18,10 +20,19 @@ fn key(id: u64) -> String {
     format!("challenges:{}", id)
 }
 
+// Each user has at most one pending chess.com challenge per guild, so it is looked up directly
+fn chesscom_key(guild_id: u64, discord_id: u64) -> String {
+    trace!("chesscom_key() called");
+    format!("challenges:chesscom:{}:{}", guild_id, discord_id)
+}
+
 impl Challenge {
     fn key(&self) -> String {
         trace!("Challenge::key() called");
-        key(self.id)
+        match self.chesscom_username {
+            Some(_) => chesscom_key(self.guild_id, self.discord_id),
+            None => key(self.id),
+        }
     }
 
     pub async fn new(pool: &db::Pool, guild_id: u64, discord_id: u64) -> Result<Challenge> {
@@ -31,6 +42,35 @@ impl Challenge {
             guild_id,
             discord_id,
             code_verifier: pkce::code_verifier(128),
+            chesscom_username: None,
+        };
+
+        challenge.save(pool).await?;
+
+        Ok(challenge)
+    }
+
+    /// Creates a challenge proving ownership of a chess.com account
+    ///
+    /// chess.com doesn't offer OAuth, so the user is instead asked to put the challenge's
+    /// `nonce()` in their public profile. Any previous chess.com challenge for the same user is
+    /// replaced.
+    pub async fn new_chesscom<U>(
+        pool: &db::Pool,
+        guild_id: u64,
+        discord_id: u64,
+        chesscom_username: U,
+    ) -> Result<Challenge>
+    where
+        U: Into<String>,
+    {
+        trace!("Challenge::new_chesscom() called");
+        let challenge = Self {
+            id: rand::random(),
+            guild_id,
+            discord_id,
+            code_verifier: pkce::code_verifier(128),
+            chesscom_username: Some(chesscom_username.into()),
         };
 
         challenge.save(pool).await?;
@@ -54,6 +94,18 @@ impl Challenge {
         }
     }
 
+    pub async fn find_chesscom(
+        pool: &db::Pool,
+        guild_id: u64,
+        discord_id: u64,
+    ) -> Result<Option<Challenge>> {
+        trace!("Challenge::find_chesscom() called");
+        match db::get(pool, chesscom_key(guild_id, discord_id)).await? {
+            Some(serialized) => Ok(Some(serde_json::from_str(&serialized)?)),
+            None => Ok(None),
+        }
+    }
+
     fn code_challenge(&self) -> String {
         trace!("Challenge::code_challenge() called");
         pkce::code_challenge(&self.code_verifier)
@@ -74,6 +126,17 @@ impl Challenge {
         self.discord_id
     }
 
+    pub fn chesscom_username(&self) -> Option<&str> {
+        trace!("Challenge::chesscom_username() called");
+        self.chesscom_username.as_deref()
+    }
+
+    /// Short code the user has to add to their chess.com profile
+    pub fn nonce(&self) -> String {
+        trace!("Challenge::nonce() called");
+        format!("liro-{}", &self.code_verifier()[..12])
+    }
+
     pub fn lichess_url(&self) -> String {
         trace!("Challenge::lichess_url() called");
         auth::oauth_url(self.code_challenge(), self.state())
@@ -110,3 +173,29 @@ impl fmt::Display for Challenge {
         write!(f, "Challenge<id={} user_id={}>", self.id, self.discord_id)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chesscom_challenges_are_keyed_by_user() {
+        let challenge = Challenge {
+            id: 1,
+            guild_id: 2,
+            discord_id: 3,
+            code_verifier: vec![],
+            chesscom_username: Some("hikaru".to_string()),
+        };
+
+        assert_eq!(challenge.key(), chesscom_key(2, 3));
+        assert_eq!(
+            Challenge {
+                chesscom_username: None,
+                ..challenge
+            }
+            .key(),
+            key(1)
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{db, lichess};
+use crate::{db, lichess, ratings};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -7,6 +7,8 @@ pub enum Error {
     Serialization(#[from] serde_json::Error),
     #[error("database error: {0}")]
     Database(#[from] db::Error),
+    #[error("rating provider error: {0}")]
+    Rating(#[from] ratings::Error),
     #[error("lichess error: {0}")]
     Lichess(#[from] lichess::Error),
 }
@@ -1,12 +1,153 @@
 use super::Result;
-use crate::db;
+use crate::{db, lichess::Format, ratings::RatingPolicy};
 use serde::{Deserialize, Serialize};
-use std::fmt;
+use std::{collections::HashMap, fmt, str::FromStr};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Guild {
     id: u64,
     name: String,
+    #[serde(default = "default_formats")]
+    formats: Vec<Format>,
+    #[serde(default)]
+    policy: RatingPolicy,
+    #[serde(default)]
+    team_roles: HashMap<String, u64>,
+    #[serde(default)]
+    role_source: RoleSource,
+    #[serde(default)]
+    role_bindings: HashMap<u64, String>,
+    #[serde(default)]
+    hysteresis: Hysteresis,
+    #[serde(default)]
+    generated_roles: Vec<u64>,
+    #[serde(default)]
+    alert_channel: Option<u64>,
+    #[serde(default)]
+    flagged_policy: FlaggedPolicy,
+}
+
+/// Slack given to members before they lose a rating role they no longer qualify for
+///
+/// A role is kept as long as the member is within `grace` points of its range, or for
+/// `delay_hours` after they left its range, whichever lasts longer.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Hysteresis {
+    #[serde(default)]
+    pub grace: i16,
+    #[serde(default)]
+    pub delay_hours: u32,
+}
+
+impl fmt::Display for Hysteresis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.grace, self.delay_hours) {
+            (0, 0) => write!(
+                f,
+                "rating roles are removed as soon as members leave their range"
+            ),
+            (grace, 0) => write!(
+                f,
+                "rating roles are kept while members are within {} points of their range",
+                grace
+            ),
+            (0, hours) => write!(
+                f,
+                "rating roles are kept for {} hours after members leave their range",
+                hours
+            ),
+            (grace, hours) => write!(
+                f,
+                "rating roles are kept while members are within {} points of their range, or \
+                for {} hours after they leave it",
+                grace, hours
+            ),
+        }
+    }
+}
+
+/// What happens to members whose linked accounts were closed or marked for violating the terms
+/// of service
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct FlaggedPolicy {
+    /// Whether their rating roles are removed
+    pub strip_roles: bool,
+    /// Whether the admins are told when a member gets flagged
+    pub notify: bool,
+}
+
+impl Default for FlaggedPolicy {
+    fn default() -> Self {
+        FlaggedPolicy {
+            strip_roles: true,
+            notify: false,
+        }
+    }
+}
+
+impl fmt::Display for FlaggedPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let roles = if self.strip_roles {
+            "lose their rating roles"
+        } else {
+            "keep their rating roles"
+        };
+        let admins = if self.notify {
+            "the admins are told"
+        } else {
+            "the admins aren't told"
+        };
+
+        write!(
+            f,
+            "members whose linked accounts were closed or marked for violating the terms of \
+            service {}, and {}",
+            roles, admins
+        )
+    }
+}
+
+/// Accounts rating roles are computed from, for members who linked several accounts
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RoleSource {
+    /// Only the account marked as primary by the member
+    Primary,
+    /// The best rating across all accounts of the member
+    #[default]
+    Best,
+}
+
+impl FromStr for RoleSource {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "primary" => Ok(RoleSource::Primary),
+            "best" => Ok(RoleSource::Best),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for RoleSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RoleSource::Primary => write!(f, "primary account"),
+            RoleSource::Best => write!(f, "best rating across all accounts"),
+        }
+    }
+}
+
+fn default_formats() -> Vec<Format> {
+    vec![
+        Format::Blitz,
+        Format::Bullet,
+        Format::Classical,
+        Format::Correspondence,
+        Format::Rapid,
+    ]
 }
 
 fn key(guild_id: u64) -> String {
@@ -28,6 +169,15 @@ impl Guild {
         let guild = Guild {
             id,
             name: name.into(),
+            formats: default_formats(),
+            policy: Default::default(),
+            team_roles: Default::default(),
+            role_source: Default::default(),
+            role_bindings: Default::default(),
+            hysteresis: Default::default(),
+            generated_roles: Default::default(),
+            alert_channel: None,
+            flagged_policy: Default::default(),
         };
 
         guild.save(pool).await?;
@@ -35,6 +185,29 @@ impl Guild {
         Ok(guild)
     }
 
+    /// Looks up the guild, creating it if it doesn't exist yet
+    ///
+    /// The name of an existing guild is updated, unless `name` is empty.
+    pub async fn find_or_create<N>(pool: &db::Pool, id: u64, name: N) -> Result<Self>
+    where
+        N: Into<String>,
+    {
+        trace!("Guild::find_or_create() called");
+        let name = name.into();
+
+        match Guild::find(pool, id).await? {
+            Some(mut guild) => {
+                if !name.is_empty() && guild.name != name {
+                    guild.name = name;
+                    guild.save(pool).await?;
+                }
+
+                Ok(guild)
+            }
+            None => Guild::new(pool, id, name).await,
+        }
+    }
+
     async fn save(&self, pool: &db::Pool) -> Result<()> {
         trace!("Guild::save() called");
         let serialized = serde_json::to_string(self)?;
@@ -43,6 +216,185 @@ impl Guild {
         Ok(())
     }
 
+    pub fn id(&self) -> u64 {
+        trace!("Guild::id() called");
+        self.id
+    }
+
+    /// Formats shown to the members of the guild when their ratings are updated
+    pub fn get_formats(&self) -> &[Format] {
+        trace!("Guild::get_formats() called");
+        &self.formats
+    }
+
+    pub async fn set_formats(&mut self, pool: &db::Pool, formats: Vec<Format>) -> Result<()> {
+        trace!("Guild::set_formats() called");
+        self.formats = formats;
+        self.save(pool).await
+    }
+
+    /// Rules deciding which ratings count towards rating roles
+    pub fn get_policy(&self) -> &RatingPolicy {
+        trace!("Guild::get_policy() called");
+        &self.policy
+    }
+
+    pub async fn set_policy(&mut self, pool: &db::Pool, policy: RatingPolicy) -> Result<()> {
+        trace!("Guild::set_policy() called");
+        self.policy = policy;
+        self.save(pool).await
+    }
+
+    /// Roles given to the members of lichess teams, keyed by team ID
+    pub fn get_team_roles(&self) -> &HashMap<String, u64> {
+        trace!("Guild::get_team_roles() called");
+        &self.team_roles
+    }
+
+    /// Maps the lichess team `team_id` to `role_id`, or removes the mapping if `role_id` is `None`
+    ///
+    /// Returns the role the team was previously mapped to.
+    pub async fn set_team_role<R>(
+        &mut self,
+        pool: &db::Pool,
+        team_id: &str,
+        role_id: R,
+    ) -> Result<Option<u64>>
+    where
+        R: Into<Option<u64>>,
+    {
+        trace!("Guild::set_team_role() called");
+        let previous = match role_id.into() {
+            Some(role_id) => self.team_roles.insert(team_id.to_string(), role_id),
+            None => self.team_roles.remove(team_id),
+        };
+
+        self.save(pool).await?;
+
+        Ok(previous)
+    }
+
+    /// Rules bound to roles by the admins, keyed by role ID
+    ///
+    /// Rules are written the same way as the role names the bot recognises.
+    pub fn get_role_bindings(&self) -> &HashMap<u64, String> {
+        trace!("Guild::get_role_bindings() called");
+        &self.role_bindings
+    }
+
+    pub async fn bind_role<R>(&mut self, pool: &db::Pool, role_id: u64, rule: R) -> Result<()>
+    where
+        R: Into<String>,
+    {
+        trace!("Guild::bind_role() called");
+        self.role_bindings.insert(role_id, rule.into());
+        self.save(pool).await
+    }
+
+    /// Removes the rule bound to `role_id`, returning it
+    pub async fn unbind_role(&mut self, pool: &db::Pool, role_id: u64) -> Result<Option<String>> {
+        trace!("Guild::unbind_role() called");
+        let rule = self.role_bindings.remove(&role_id);
+
+        if rule.is_some() {
+            self.save(pool).await?;
+        }
+
+        Ok(rule)
+    }
+
+    /// Checks whether `role_id` is explicitly bound to a rule or to a lichess team
+    pub fn is_bound(&self, role_id: u64) -> bool {
+        trace!("Guild::is_bound() called");
+        self.role_bindings.contains_key(&role_id) || self.team_roles.values().any(|&r| r == role_id)
+    }
+
+    /// Removes every binding and lichess team mapping of `role_id`, e.g. once it is deleted
+    pub async fn forget_role(&mut self, pool: &db::Pool, role_id: u64) -> Result<()> {
+        trace!("Guild::forget_role() called");
+        if self.is_bound(role_id) || self.generated_roles.contains(&role_id) {
+            self.role_bindings.remove(&role_id);
+            self.team_roles.retain(|_, &mut r| r != role_id);
+            self.generated_roles.retain(|&r| r != role_id);
+            self.save(pool).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Roles created by `/roles generate`
+    pub fn get_generated_roles(&self) -> &[u64] {
+        trace!("Guild::get_generated_roles() called");
+        &self.generated_roles
+    }
+
+    pub async fn add_generated_roles(&mut self, pool: &db::Pool, role_ids: &[u64]) -> Result<()> {
+        trace!("Guild::add_generated_roles() called");
+        self.generated_roles.extend_from_slice(role_ids);
+        self.save(pool).await
+    }
+
+    /// Forgets every generated role, returning them
+    pub async fn take_generated_roles(&mut self, pool: &db::Pool) -> Result<Vec<u64>> {
+        trace!("Guild::take_generated_roles() called");
+        let role_ids = std::mem::take(&mut self.generated_roles);
+        self.save(pool).await?;
+
+        Ok(role_ids)
+    }
+
+    pub fn get_role_source(&self) -> RoleSource {
+        trace!("Guild::get_role_source() called");
+        self.role_source
+    }
+
+    pub async fn set_role_source(&mut self, pool: &db::Pool, source: RoleSource) -> Result<()> {
+        trace!("Guild::set_role_source() called");
+        self.role_source = source;
+        self.save(pool).await
+    }
+
+    /// Slack given to members before they lose a rating role
+    pub fn get_hysteresis(&self) -> Hysteresis {
+        trace!("Guild::get_hysteresis() called");
+        self.hysteresis
+    }
+
+    pub async fn set_hysteresis(&mut self, pool: &db::Pool, hysteresis: Hysteresis) -> Result<()> {
+        trace!("Guild::set_hysteresis() called");
+        self.hysteresis = hysteresis;
+        self.save(pool).await
+    }
+
+    /// Channel the bot posts warnings for the admins to
+    pub fn get_alert_channel(&self) -> Option<u64> {
+        trace!("Guild::get_alert_channel() called");
+        self.alert_channel
+    }
+
+    pub async fn set_alert_channel(&mut self, pool: &db::Pool, channel_id: u64) -> Result<()> {
+        trace!("Guild::set_alert_channel() called");
+        self.alert_channel = Some(channel_id);
+        self.save(pool).await
+    }
+
+    /// What happens to members whose linked accounts were closed or marked for violating the
+    /// terms of service
+    pub fn get_flagged_policy(&self) -> FlaggedPolicy {
+        trace!("Guild::get_flagged_policy() called");
+        self.flagged_policy
+    }
+
+    pub async fn set_flagged_policy(
+        &mut self,
+        pool: &db::Pool,
+        flagged_policy: FlaggedPolicy,
+    ) -> Result<()> {
+        trace!("Guild::set_flagged_policy() called");
+        self.flagged_policy = flagged_policy;
+        self.save(pool).await
+    }
+
     pub async fn count(pool: &db::Pool) -> Result<usize> {
         trace!("Guild::count() called");
 
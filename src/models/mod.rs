@@ -1,9 +1,21 @@
+mod account;
 mod challenge;
 mod error;
 mod guild;
 mod user;
 
+pub use account::Account;
 pub use challenge::Challenge;
 pub use error::{Error, Result};
-pub use guild::Guild;
+pub use guild::{Guild, Hysteresis, RoleSource};
 pub use user::User;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Current time, in seconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -1,17 +1,47 @@
-use super::Result;
+use super::{now, Account, Error, Hysteresis, Result};
 use crate::{
-    db,
-    lichess::{self, Format},
+    chesscom, db, lichess,
+    ratings::{self, Provider, RatingProvider, Ratings},
 };
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, fmt};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct User {
     guild_id: u64,
     discord_id: u64,
-    lichess_username: String,
-    ratings: HashMap<Format, i16>,
+    #[serde(default)]
+    accounts: Vec<Account>,
+    #[serde(default)]
+    role_states: HashMap<u64, RoleState>,
+    /// When the ratings and roles of the user were last synced, in seconds since the Unix epoch
+    #[serde(default)]
+    last_synced: Option<u64>,
+    /// When a linked account was first found closed or marked for violating the terms of
+    /// service, in seconds since the Unix epoch
+    #[serde(default)]
+    flagged_at: Option<u64>,
+    // Users stored before multiple accounts were supported had a single account per provider.
+    // These fields are only read, and moved to `accounts` when the user is loaded.
+    #[serde(default, skip_serializing)]
+    lichess_username: Option<String>,
+    #[serde(default, skip_serializing)]
+    chesscom_username: Option<String>,
+    #[serde(default, skip_serializing)]
+    ratings: Ratings,
+    #[serde(default, skip_serializing)]
+    peaks: Ratings,
+}
+
+/// Rating role held by a user, with the times used to delay its removal
+///
+/// Times are in seconds since the Unix epoch.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+struct RoleState {
+    assigned_at: u64,
+    /// When the user left the range of the role, if they are out of it
+    #[serde(default)]
+    out_of_range_since: Option<u64>,
 }
 
 fn key(guild_id: u64, discord_id: u64) -> String {
@@ -25,28 +55,142 @@ impl User {
         key(self.guild_id, self.discord_id)
     }
 
-    pub async fn new<U>(
+    fn from_json(serialized: &str) -> Result<User> {
+        trace!("User::from_json() called");
+        let mut user: User = serde_json::from_str(serialized)?;
+        user.migrate_legacy_accounts();
+        Ok(user)
+    }
+
+    fn migrate_legacy_accounts(&mut self) {
+        trace!("User::migrate_legacy_accounts() called");
+        let legacy = [
+            (Provider::Lichess, self.lichess_username.take()),
+            (Provider::Chesscom, self.chesscom_username.take()),
+        ];
+
+        for (provider, username) in legacy {
+            if let Some(username) = username {
+                let mut account = Account::new(provider, username);
+                account.set_ratings(
+                    self.ratings
+                        .iter()
+                        .filter(|(p, _, _)| *p == provider)
+                        .map(|(_, f, r)| (f, *r))
+                        .collect(),
+                );
+                account.set_peaks(
+                    self.peaks
+                        .iter()
+                        .filter(|(p, _, _)| *p == provider)
+                        .map(|(_, f, r)| (f, r.rating()))
+                        .collect(),
+                );
+                self.accounts.push(account);
+            }
+        }
+
+        self.ratings = Default::default();
+        self.peaks = Default::default();
+        self.ensure_primary();
+    }
+
+    /// Makes the first account primary if none of them is
+    fn ensure_primary(&mut self) {
+        trace!("User::ensure_primary() called");
+        if !self.accounts.iter().any(|a| a.is_primary()) {
+            if let Some(account) = self.accounts.first_mut() {
+                account.set_primary(true);
+            }
+        }
+    }
+
+    /// Links the `username` account from `provider` to the Discord user
+    ///
+    /// The user is created if they didn't link any account yet. The first account linked
+    /// becomes the primary account.
+    pub async fn link<U>(
         pool: &db::Pool,
         guild_id: u64,
         discord_id: u64,
-        lichess_username: U,
+        provider: Provider,
+        username: U,
     ) -> Result<User>
     where
         U: Into<String>,
     {
-        trace!("User::new() called");
-        let user = User {
-            guild_id,
-            discord_id,
-            lichess_username: lichess_username.into(),
-            ratings: Default::default(),
+        trace!("User::link() called");
+        let mut user = match User::find(pool, guild_id, discord_id).await? {
+            Some(user) => user,
+            None => User {
+                guild_id,
+                discord_id,
+                accounts: vec![],
+                role_states: Default::default(),
+                last_synced: None,
+                flagged_at: None,
+                lichess_username: None,
+                chesscom_username: None,
+                ratings: Default::default(),
+                peaks: Default::default(),
+            },
         };
 
+        let username = username.into();
+        if !user
+            .accounts
+            .iter()
+            .any(|a| a.provider() == provider && a.username().eq_ignore_ascii_case(&username))
+        {
+            user.accounts.push(Account::new(provider, username));
+            user.ensure_primary();
+        }
+
         user.save(pool).await?;
 
         Ok(user)
     }
 
+    /// Removes the account designated by `name`, see [`Account::is_named`]
+    ///
+    /// If the primary account is removed, the next account becomes primary. The user is deleted
+    /// along with their last account.
+    pub async fn unlink(&mut self, pool: &db::Pool, name: &str) -> Result<Option<Account>> {
+        trace!("User::unlink() called");
+        let index = match self.accounts.iter().position(|a| a.is_named(name)) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+
+        let account = self.accounts.remove(index);
+
+        if self.accounts.is_empty() {
+            self.delete(pool).await?;
+        } else {
+            self.ensure_primary();
+            self.save(pool).await?;
+        }
+
+        Ok(Some(account))
+    }
+
+    /// Makes the account designated by `name` the primary account, see [`Account::is_named`]
+    pub async fn set_primary(&mut self, pool: &db::Pool, name: &str) -> Result<Option<&Account>> {
+        trace!("User::set_primary() called");
+        let index = match self.accounts.iter().position(|a| a.is_named(name)) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+
+        for (i, account) in self.accounts.iter_mut().enumerate() {
+            account.set_primary(i == index);
+        }
+
+        self.save(pool).await?;
+
+        Ok(self.accounts.get(index))
+    }
+
     async fn save(&self, pool: &db::Pool) -> Result<()> {
         trace!("User::save() called");
         debug!("Saving {}", &self);
@@ -62,7 +206,7 @@ impl User {
 
         match db::get(pool, key(guild_id, discord_id)).await? {
             Some(serialized) => {
-                let user = serde_json::from_str(&serialized)?;
+                let user = User::from_json(&serialized)?;
                 debug!("Found {}", user);
                 Ok(Some(user))
             }
@@ -73,9 +217,11 @@ impl User {
         }
     }
 
+    /// Finds the user who linked the `username` account from `provider`
     pub async fn find_by_username<U>(
         pool: &db::Pool,
         guild_id: u64,
+        provider: Provider,
         username: U,
     ) -> Result<Option<User>>
     where
@@ -84,35 +230,197 @@ impl User {
         trace!("User::find_by_username() called");
         let users = User::fetch_all(pool, guild_id).await?;
 
-        Ok(users
-            .into_iter()
-            .find(|u| u.get_lichess_username() == username.as_ref()))
+        Ok(users.into_iter().find(|u| {
+            u.accounts.iter().any(|a| {
+                a.provider() == provider && a.username().eq_ignore_ascii_case(username.as_ref())
+            })
+        }))
+    }
+
+    pub fn discord_id(&self) -> u64 {
+        trace!("User::discord_id() called");
+        self.discord_id
+    }
+
+    pub fn get_accounts(&self) -> &[Account] {
+        trace!("User::get_accounts() called");
+        &self.accounts
+    }
+
+    pub fn get_primary_account(&self) -> Option<&Account> {
+        trace!("User::get_primary_account() called");
+        self.accounts.iter().find(|a| a.is_primary())
+    }
+
+    /// Works out which rating roles the user keeps, and records them
+    ///
+    /// `matched` are the roles the user qualifies for, and `in_grace` the rating roles they would
+    /// qualify for if ranges were widened by the guild's grace band. A rating role the user holds
+    /// is kept while it is in the grace band, or until `hysteresis.delay_hours` went by since they
+    /// left its range.
+    pub async fn update_role_states(
+        &mut self,
+        pool: &db::Pool,
+        matched: &[u64],
+        in_grace: &[u64],
+        hysteresis: Hysteresis,
+    ) -> Result<Vec<u64>> {
+        trace!("User::update_role_states() called");
+        let kept = self.apply_role_states(
+            matched,
+            in_grace,
+            hysteresis.delay_hours as u64 * 3600,
+            now(),
+        );
+
+        self.save(pool).await?;
+
+        Ok(kept)
+    }
+
+    /// Works out which rating roles the user would keep, like `update_role_states`, without
+    /// recording anything
+    pub fn preview_role_states(
+        &self,
+        matched: &[u64],
+        in_grace: &[u64],
+        hysteresis: Hysteresis,
+    ) -> Vec<u64> {
+        trace!("User::preview_role_states() called");
+        self.clone().apply_role_states(
+            matched,
+            in_grace,
+            hysteresis.delay_hours as u64 * 3600,
+            now(),
+        )
+    }
+
+    fn apply_role_states(
+        &mut self,
+        matched: &[u64],
+        in_grace: &[u64],
+        delay: u64,
+        now: u64,
+    ) -> Vec<u64> {
+        trace!("User::apply_role_states() called");
+        let mut kept = matched.to_vec();
+
+        self.role_states.retain(|role_id, state| {
+            if matched.contains(role_id) {
+                state.out_of_range_since = None;
+                return true;
+            }
+
+            let since = *state.out_of_range_since.get_or_insert(now);
+            if in_grace.contains(role_id) || now.saturating_sub(since) < delay {
+                kept.push(*role_id);
+                true
+            } else {
+                false
+            }
+        });
+
+        // Only rating roles, which are in the grace band whenever they match, are tracked
+        for role_id in matched.iter().filter(|r| in_grace.contains(r)) {
+            self.role_states.entry(*role_id).or_insert(RoleState {
+                assigned_at: now,
+                out_of_range_since: None,
+            });
+        }
+
+        kept
+    }
+
+    /// Checks whether the user wasn't synced for at least `interval` seconds
+    pub fn is_sync_due(&self, interval: u64) -> bool {
+        trace!("User::is_sync_due() called");
+        self.is_sync_due_at(interval, now())
     }
 
-    pub fn get_lichess_username(&self) -> &str {
-        trace!("User::lichess_username() called");
-        &self.lichess_username
+    fn is_sync_due_at(&self, interval: u64, now: u64) -> bool {
+        match self.last_synced {
+            Some(last_synced) => now.saturating_sub(last_synced) >= interval,
+            None => true,
+        }
+    }
+
+    /// Records that the ratings and roles of the user were just synced
+    pub async fn mark_synced(&mut self, pool: &db::Pool) -> Result<()> {
+        trace!("User::mark_synced() called");
+        self.last_synced = Some(now());
+        self.save(pool).await
     }
 
+    /// Whether a linked account is closed or marked for violating the terms of service
+    pub fn is_flagged(&self) -> bool {
+        trace!("User::is_flagged() called");
+        self.flagged_at.is_some()
+    }
+
+    fn update_flag_at(&mut self, now: u64) {
+        self.flagged_at = if self.accounts.iter().any(|a| a.is_flagged()) {
+            self.flagged_at.or(Some(now))
+        } else {
+            None
+        };
+    }
+
+    /// Refreshes every linked account from its matching provider, and flags the user if any of
+    /// them was closed or marked for violating the terms of service
+    ///
+    /// Peak ratings fetched less than `max_age` seconds ago are kept, see [`Account::update`].
     pub async fn update_ratings(
         &mut self,
         pool: &db::Pool,
-        lichess: &lichess::Client,
-    ) -> Result<&HashMap<Format, i16>> {
+        providers: &[&dyn RatingProvider],
+        max_age: u64,
+    ) -> Result<()> {
         trace!("User::update_ratings() called");
 
-        self.ratings = lichess
-            .fetch_user_ratings(self.get_lichess_username())
-            .await?;
-
-        self.save(pool).await?;
+        for account in self.accounts.iter_mut() {
+            if let Some(provider) = providers
+                .iter()
+                .find(|p| p.provider() == account.provider())
+            {
+                match account.update(*provider, max_age).await {
+                    // Renamed or deleted accounts shouldn't keep the other ones from updating
+                    Err(Error::Rating(ratings::Error::Lichess(lichess::Error::NotFound)))
+                    | Err(Error::Rating(ratings::Error::Chesscom(chesscom::Error::NotFound))) => {
+                        warn!(
+                            "Skipping {} of discord_id={}: account not found",
+                            account, self.discord_id
+                        );
+                    }
+                    result => result?,
+                }
+            }
+        }
+        self.update_flag_at(now());
 
-        Ok(&self.ratings)
+        self.save(pool).await
     }
 
-    pub fn get_ratings(&self) -> &HashMap<Format, i16> {
-        trace!("User::get_ratings() called");
-        &self.ratings
+    /// Refreshes the teams of every linked account from its matching provider
+    ///
+    /// Teams fetched less than `max_age` seconds ago are kept.
+    pub async fn update_teams(
+        &mut self,
+        pool: &db::Pool,
+        providers: &[&dyn RatingProvider],
+        max_age: u64,
+    ) -> Result<()> {
+        trace!("User::update_teams() called");
+
+        for account in self.accounts.iter_mut() {
+            if let Some(provider) = providers
+                .iter()
+                .find(|p| p.provider() == account.provider())
+            {
+                account.update_teams(*provider, max_age).await?;
+            }
+        }
+
+        self.save(pool).await
     }
 
     pub async fn fetch_all(pool: &db::Pool, guild_id: u64) -> Result<Vec<User>> {
@@ -125,8 +433,8 @@ impl User {
             Ok(db::mget(pool, keys)
                 .await?
                 .iter()
-                .map(|s| serde_json::from_str(s))
-                .collect::<std::result::Result<Vec<_>, _>>()?)
+                .map(|s| User::from_json(s))
+                .collect::<Result<Vec<_>>>()?)
         } else {
             Ok(Default::default())
         }
@@ -171,8 +479,132 @@ impl fmt::Display for User {
         trace!("User::fmt() called");
         write!(
             f,
-            "User<discord_id={} lichess_username={} ratings={:?}>",
-            self.discord_id, self.lichess_username, self.ratings
+            "User<discord_id={} accounts={:?}>",
+            self.discord_id, self.accounts
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lichess::Format;
+
+    #[test]
+    fn legacy_users_are_migrated_to_accounts() {
+        let user = User::from_json(
+            r#"{"guild_id":1,"discord_id":2,"lichess_username":"foo","ratings":{"blitz":1500},
+            "chesscom_username":"bar","peaks":{"chesscom":{"rapid":1700}}}"#,
         )
+        .unwrap();
+
+        let accounts = user.get_accounts();
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts[0].provider(), Provider::Lichess);
+        assert_eq!(accounts[0].username(), "foo");
+        assert!(accounts[0].is_primary());
+        assert_eq!(accounts[0].get_ratings()[&Format::Blitz].rating(), 1500);
+        assert_eq!(accounts[1].username(), "bar");
+        assert!(!accounts[1].is_primary());
+        assert_eq!(accounts[1].get_peaks()[&Format::Rapid], 1700);
+    }
+
+    #[test]
+    fn migrated_users_are_read_back_identically() {
+        let user =
+            User::from_json(r#"{"guild_id":1,"discord_id":2,"lichess_username":"foo"}"#).unwrap();
+        let serialized = serde_json::to_string(&user).unwrap();
+
+        assert_eq!(
+            User::from_json(&serialized).unwrap().get_accounts(),
+            user.get_accounts()
+        );
+    }
+
+    #[test]
+    fn rating_roles_are_removed_without_hysteresis() {
+        let mut user = User::from_json(r#"{"guild_id":1,"discord_id":2}"#).unwrap();
+
+        assert_eq!(
+            user.apply_role_states(&[10, 20], &[10], 0, 100),
+            vec![10, 20]
+        );
+        assert_eq!(user.role_states[&10].assigned_at, 100);
+        assert!(!user.role_states.contains_key(&20));
+
+        assert!(user.apply_role_states(&[], &[], 0, 200).is_empty());
+        assert!(user.role_states.is_empty());
+    }
+
+    #[test]
+    fn syncs_are_due_once_the_interval_went_by() {
+        let mut user = User::from_json(r#"{"guild_id":1,"discord_id":2}"#).unwrap();
+        assert!(user.is_sync_due_at(3600, 100));
+
+        user.last_synced = Some(100);
+        assert!(!user.is_sync_due_at(3600, 3699));
+        assert!(user.is_sync_due_at(3600, 3700));
+    }
+
+    #[test]
+    fn users_are_flagged_while_an_account_is() {
+        let mut user = User::from_json(
+            r#"{"guild_id":1,"discord_id":2,"accounts":[
+                {"provider":"lichess","username":"foo","tos_violation":true},
+                {"provider":"chesscom","username":"bar"}]}"#,
+        )
+        .unwrap();
+        assert!(!user.is_flagged());
+
+        user.update_flag_at(100);
+        user.update_flag_at(200);
+        assert_eq!(user.flagged_at, Some(100));
+
+        user.accounts.clear();
+        user.update_flag_at(300);
+        assert!(!user.is_flagged());
+    }
+
+    #[test]
+    fn role_state_previews_are_not_recorded() {
+        let mut user = User::from_json(r#"{"guild_id":1,"discord_id":2}"#).unwrap();
+        user.apply_role_states(&[10], &[10], 0, 100);
+        let hysteresis = Hysteresis {
+            grace: 0,
+            delay_hours: 1,
+        };
+
+        assert_eq!(user.preview_role_states(&[], &[], hysteresis), vec![10]);
+        assert_eq!(user.role_states[&10].out_of_range_since, None);
+    }
+
+    #[test]
+    fn rating_roles_are_kept_in_the_grace_band() {
+        let mut user = User::from_json(r#"{"guild_id":1,"discord_id":2}"#).unwrap();
+        user.apply_role_states(&[10], &[10], 0, 100);
+
+        assert_eq!(user.apply_role_states(&[], &[10], 0, 200), vec![10]);
+        assert_eq!(user.role_states[&10].out_of_range_since, Some(200));
+
+        assert_eq!(user.apply_role_states(&[10], &[10], 0, 300), vec![10]);
+        assert_eq!(user.role_states[&10].assigned_at, 100);
+        assert_eq!(user.role_states[&10].out_of_range_since, None);
+    }
+
+    #[test]
+    fn rating_roles_are_kept_until_the_delay_went_by() {
+        let mut user = User::from_json(r#"{"guild_id":1,"discord_id":2}"#).unwrap();
+        user.apply_role_states(&[10], &[10], 3600, 100);
+
+        assert_eq!(user.apply_role_states(&[], &[], 3600, 200), vec![10]);
+        assert_eq!(user.apply_role_states(&[], &[], 3600, 3700), vec![10]);
+        assert!(user.apply_role_states(&[], &[], 3600, 3800).is_empty());
+    }
+
+    #[test]
+    fn grace_is_not_given_to_roles_the_user_never_held() {
+        let mut user = User::from_json(r#"{"guild_id":1,"discord_id":2}"#).unwrap();
+
+        assert!(user.apply_role_states(&[], &[10], 3600, 100).is_empty());
     }
 }
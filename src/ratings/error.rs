@@ -0,0 +1,12 @@
+use crate::{chesscom, lichess};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("lichess error: {0}")]
+    Lichess(#[from] lichess::Error),
+    #[error("chess.com error: {0}")]
+    Chesscom(#[from] chesscom::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
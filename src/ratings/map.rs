@@ -0,0 +1,125 @@
+use super::{Provider, Rating};
+use crate::lichess::Format;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{collections::HashMap, iter::FromIterator};
+
+/// Ratings of a user, keyed by the provider they come from and their format
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Ratings(HashMap<Provider, HashMap<Format, Rating>>);
+
+impl Ratings {
+    // Ratings are only looked up one by one in tests, the bot walks through all of them
+    #[cfg(test)]
+    pub fn get(&self, provider: Provider, format: Format) -> Option<&Rating> {
+        self.0.get(&provider).and_then(|r| r.get(&format))
+    }
+
+    /// Replaces all the ratings known for `provider`
+    pub fn set(&mut self, provider: Provider, ratings: HashMap<Format, Rating>) {
+        self.0.insert(provider, ratings);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Provider, Format, &Rating)> {
+        self.0
+            .iter()
+            .flat_map(|(&p, r)| r.iter().map(move |(&f, rating)| (p, f, rating)))
+    }
+}
+
+impl<R> FromIterator<(Provider, Format, R)> for Ratings
+where
+    R: Into<Rating>,
+{
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (Provider, Format, R)>,
+    {
+        let mut ratings = Ratings::default();
+        for (provider, format, rating) in iter {
+            ratings
+                .0
+                .entry(provider)
+                .or_default()
+                .insert(format, rating.into());
+        }
+        ratings
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredRatings {
+    Current(HashMap<Provider, HashMap<Format, Rating>>),
+    // Ratings stored before chess.com support were lichess ratings keyed by format only
+    Legacy(HashMap<Format, Rating>),
+}
+
+impl<'de> Deserialize<'de> for Ratings {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(match StoredRatings::deserialize(deserializer)? {
+            StoredRatings::Current(ratings) => Ratings(ratings),
+            StoredRatings::Legacy(ratings) => {
+                let mut r = Ratings::default();
+                r.set(Provider::Lichess, ratings);
+                r
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ratings_are_keyed_by_provider_and_format() {
+        let ratings: Ratings = [
+            (Provider::Lichess, Format::Blitz, 1500),
+            (Provider::Chesscom, Format::Blitz, 1200),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+
+        assert_eq!(
+            ratings.get(Provider::Lichess, Format::Blitz),
+            Some(&Rating::from(1500))
+        );
+        assert_eq!(
+            ratings.get(Provider::Chesscom, Format::Blitz),
+            Some(&Rating::from(1200))
+        );
+        assert_eq!(ratings.get(Provider::Chesscom, Format::Rapid), None);
+    }
+
+    #[test]
+    fn ratings_can_be_read_back_after_serialization() {
+        let ratings: Ratings = [(Provider::Chesscom, Format::Rapid, 1200)]
+            .iter()
+            .cloned()
+            .collect();
+        let serialized = serde_json::to_string(&ratings).unwrap();
+
+        assert_eq!(
+            serde_json::from_str::<Ratings>(&serialized).unwrap(),
+            ratings
+        );
+    }
+
+    #[test]
+    fn legacy_ratings_are_read_as_lichess_ratings() {
+        let ratings: Ratings = serde_json::from_str(r#"{"blitz":1500,"rapid":1600}"#).unwrap();
+
+        assert_eq!(
+            ratings.get(Provider::Lichess, Format::Blitz),
+            Some(&Rating::from(1500))
+        );
+        assert_eq!(
+            ratings.get(Provider::Lichess, Format::Rapid),
+            Some(&Rating::from(1600))
+        );
+    }
+}
@@ -0,0 +1,15 @@
+mod error;
+mod map;
+mod policy;
+mod profile;
+mod provider;
+mod rating;
+mod title;
+
+pub use error::{Error, Result};
+pub use map::Ratings;
+pub use policy::RatingPolicy;
+pub use profile::PlayerProfile;
+pub use provider::{Provider, RatingProvider};
+pub use rating::Rating;
+pub use title::Title;
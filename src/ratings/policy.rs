@@ -0,0 +1,173 @@
+use super::Rating;
+use crate::lichess::Format;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt};
+
+/// Reason why a rating doesn't count towards rating roles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ineligibility {
+    Provisional,
+    NotEnoughGames { games: u32, required: u32 },
+    DeviationTooHigh { rd: i16, max: i16 },
+}
+
+impl fmt::Display for Ineligibility {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Ineligibility::Provisional => write!(f, "provisional rating"),
+            Ineligibility::NotEnoughGames { games, required } => {
+                write!(f, "{} of {} required games played", games, required)
+            }
+            Ineligibility::DeviationTooHigh { rd, max } => {
+                write!(f, "rating deviation {} is above {}", rd, max)
+            }
+        }
+    }
+}
+
+/// Per-guild rules deciding which ratings count towards rating roles
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RatingPolicy {
+    #[serde(default)]
+    allow_provisional: bool,
+    #[serde(default)]
+    min_games: HashMap<Format, u32>,
+    #[serde(default)]
+    max_rd: Option<i16>,
+}
+
+impl RatingPolicy {
+    /// Checks whether `rating` may be used to hand out roles for `format`
+    ///
+    /// Ratings for which the provider doesn't publish a deviation always pass the deviation
+    /// check.
+    pub fn check(&self, format: Format, rating: &Rating) -> Result<(), Ineligibility> {
+        trace!("RatingPolicy::check() called");
+        if rating.is_provisional() && !self.allow_provisional {
+            return Err(Ineligibility::Provisional);
+        }
+
+        if let Some(&required) = self.min_games.get(&format) {
+            if rating.games() < required {
+                return Err(Ineligibility::NotEnoughGames {
+                    games: rating.games(),
+                    required,
+                });
+            }
+        }
+
+        if let (Some(max), Some(rd)) = (self.max_rd, rating.rd()) {
+            if rd > max {
+                return Err(Ineligibility::DeviationTooHigh { rd, max });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn set_allow_provisional(&mut self, allow_provisional: bool) {
+        self.allow_provisional = allow_provisional;
+    }
+
+    /// Sets the minimum number of games for `format`, a value of 0 removes the requirement
+    pub fn set_min_games(&mut self, format: Format, games: u32) {
+        if games == 0 {
+            self.min_games.remove(&format);
+        } else {
+            self.min_games.insert(format, games);
+        }
+    }
+
+    pub fn set_max_rd<R>(&mut self, max_rd: R)
+    where
+        R: Into<Option<i16>>,
+    {
+        self.max_rd = max_rd.into();
+    }
+}
+
+impl fmt::Display for RatingPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "provisional ratings are {}",
+            if self.allow_provisional {
+                "allowed"
+            } else {
+                "ignored"
+            }
+        )?;
+
+        let mut min_games: Vec<_> = self.min_games.iter().collect();
+        min_games.sort_by_key(|(format, _)| format.key());
+        for (format, games) in min_games {
+            write!(f, ", {} needs {} games", format.key(), games)?;
+        }
+
+        match self.max_rd {
+            Some(max_rd) => write!(f, ", rating deviation must be at most {}", max_rd),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_policy_refuses_provisional_ratings() {
+        let policy = RatingPolicy::default();
+
+        assert_eq!(
+            policy.check(Format::Blitz, &Rating::new(1500, Some(150), 0, 3, true)),
+            Err(Ineligibility::Provisional)
+        );
+        assert!(policy
+            .check(Format::Blitz, &Rating::new(1500, Some(50), 0, 300, false))
+            .is_ok());
+    }
+
+    #[test]
+    fn policy_can_allow_provisional_ratings() {
+        let mut policy = RatingPolicy::default();
+        policy.set_allow_provisional(true);
+
+        assert!(policy
+            .check(Format::Blitz, &Rating::new(1500, Some(150), 0, 3, true))
+            .is_ok());
+    }
+
+    #[test]
+    fn policy_requires_minimum_games_per_format() {
+        let mut policy = RatingPolicy::default();
+        policy.set_min_games(Format::Blitz, 50);
+        let rating = Rating::new(1500, Some(50), 0, 20, false);
+
+        assert_eq!(
+            policy.check(Format::Blitz, &rating),
+            Err(Ineligibility::NotEnoughGames {
+                games: 20,
+                required: 50
+            })
+        );
+        assert!(policy.check(Format::Rapid, &rating).is_ok());
+
+        policy.set_min_games(Format::Blitz, 0);
+        assert!(policy.check(Format::Blitz, &rating).is_ok());
+    }
+
+    #[test]
+    fn policy_requires_maximum_deviation() {
+        let mut policy = RatingPolicy::default();
+        policy.set_max_rd(60);
+
+        assert_eq!(
+            policy.check(Format::Blitz, &Rating::new(1500, Some(75), 0, 300, false)),
+            Err(Ineligibility::DeviationTooHigh { rd: 75, max: 60 })
+        );
+        assert!(policy
+            .check(Format::Blitz, &Rating::new(1500, None, 0, 300, false))
+            .is_ok());
+    }
+}
@@ -0,0 +1,20 @@
+use super::{Rating, Title};
+use crate::lichess::Format;
+use std::collections::HashMap;
+
+/// Everything a provider publishes about a player that roles can be based on
+#[derive(Debug, Clone, Default)]
+pub struct PlayerProfile {
+    pub ratings: HashMap<Format, Rating>,
+    /// Highest ratings ever reached, for providers that serve them along with the profile
+    pub peaks: Option<HashMap<Format, i16>>,
+    pub title: Option<Title>,
+    pub patron: bool,
+    pub verified: bool,
+    /// When the account was created, in seconds since the Unix epoch
+    pub created_at: Option<u64>,
+    /// Whether the account was closed, in which case it comes without ratings
+    pub closed: bool,
+    /// Whether the account was marked for violating the terms of service of the provider
+    pub tos_violation: bool,
+}
@@ -0,0 +1,125 @@
+use super::{PlayerProfile, Result};
+use crate::lichess::Format;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    Lichess,
+    Chesscom,
+}
+
+impl Provider {
+    /// Human readable name of the website, as opposed to the identifier used in role names
+    pub fn name(&self) -> &'static str {
+        match self {
+            Provider::Lichess => "lichess",
+            Provider::Chesscom => "chess.com",
+        }
+    }
+
+    pub fn url(&self) -> &'static str {
+        match self {
+            Provider::Lichess => "https://lichess.org",
+            Provider::Chesscom => "https://www.chess.com",
+        }
+    }
+
+    pub fn profile_url<U>(&self, username: U) -> String
+    where
+        U: AsRef<str>,
+    {
+        match self {
+            Provider::Lichess => format!("https://lichess.org/@/{}", username.as_ref()),
+            Provider::Chesscom => format!("https://www.chess.com/member/{}", username.as_ref()),
+        }
+    }
+
+    /// Lowest rating a player can have on the provider
+    pub fn rating_floor(&self) -> i16 {
+        match self {
+            Provider::Lichess => 400,
+            Provider::Chesscom => 100,
+        }
+    }
+
+    /// Formats for which the provider keeps a rating
+    pub fn formats(&self) -> Vec<Format> {
+        match self {
+            Provider::Lichess => Format::iter().collect(),
+            Provider::Chesscom => vec![
+                Format::Blitz,
+                Format::Bullet,
+                Format::Correspondence,
+                Format::Rapid,
+            ],
+        }
+    }
+}
+
+impl FromStr for Provider {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "lichess" => Ok(Provider::Lichess),
+            "chesscom" | "chess.com" => Ok(Provider::Chesscom),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Display for Provider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Provider::Lichess => "lichess",
+            Provider::Chesscom => "chesscom",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A source of ratings, such as a chess website
+#[async_trait]
+pub trait RatingProvider: Sync {
+    fn provider(&self) -> Provider;
+
+    /// Fetches the current ratings of the player, along with their title and account flags
+    async fn fetch_profile(&self, username: &str) -> Result<PlayerProfile>;
+
+    /// Fetches the highest rating ever reached in every format
+    async fn fetch_peaks(&self, username: &str) -> Result<HashMap<Format, i16>>;
+
+    /// Fetches the IDs of the teams the player is a member of, for providers that have teams
+    async fn fetch_teams(&self, _username: &str) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn provider_can_be_parsed_from_string() {
+        assert_eq!("lichess".parse::<Provider>().unwrap(), Provider::Lichess);
+        assert_eq!("chesscom".parse::<Provider>().unwrap(), Provider::Chesscom);
+        assert_eq!("chess.com".parse::<Provider>().unwrap(), Provider::Chesscom);
+    }
+
+    #[test]
+    fn provider_parser_reports_error() {
+        assert!("fics".parse::<Provider>().is_err());
+    }
+
+    #[test]
+    fn provider_display_can_be_parsed_back() {
+        for provider in Provider::iter() {
+            assert_eq!(provider.to_string().parse::<Provider>().unwrap(), provider);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+
+/// A rating in a single format, along with the details needed to judge how reliable it is
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(remote = "Self")]
+pub struct Rating {
+    rating: i16,
+    #[serde(default)]
+    rd: Option<i16>,
+    #[serde(default)]
+    progress: i16,
+    #[serde(default)]
+    games: u32,
+    #[serde(default)]
+    provisional: bool,
+}
+
+impl Rating {
+    pub fn new(rating: i16, rd: Option<i16>, progress: i16, games: u32, provisional: bool) -> Self {
+        Rating {
+            rating,
+            rd,
+            progress,
+            games,
+            provisional,
+        }
+    }
+
+    pub fn rating(&self) -> i16 {
+        self.rating
+    }
+
+    /// Rating deviation, when the provider publishes it
+    pub fn rd(&self) -> Option<i16> {
+        self.rd
+    }
+
+    /// Rating change over the last twelve games
+    pub fn progress(&self) -> i16 {
+        self.progress
+    }
+
+    pub fn games(&self) -> u32 {
+        self.games
+    }
+
+    pub fn is_provisional(&self) -> bool {
+        self.provisional
+    }
+}
+
+impl From<i16> for Rating {
+    fn from(rating: i16) -> Self {
+        Rating::new(rating, None, 0, 0, false)
+    }
+}
+
+impl Serialize for Rating {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        Rating::serialize(self, serializer)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredRating {
+    #[serde(with = "Rating")]
+    Current(Rating),
+    // Ratings used to be stored as a bare number
+    Legacy(i16),
+}
+
+impl<'de> Deserialize<'de> for Rating {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(match StoredRating::deserialize(deserializer)? {
+            StoredRating::Current(rating) => rating,
+            StoredRating::Legacy(rating) => rating.into(),
+        })
+    }
+}
+
+impl fmt::Display for Rating {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.provisional {
+            write!(f, "{}?", self.rating)
+        } else {
+            write!(f, "{}", self.rating)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rating_can_be_read_back_after_serialization() {
+        let rating = Rating::new(1500, Some(60), -12, 340, false);
+        let serialized = serde_json::to_string(&rating).unwrap();
+
+        assert_eq!(serde_json::from_str::<Rating>(&serialized).unwrap(), rating);
+    }
+
+    #[test]
+    fn legacy_ratings_are_read_from_bare_numbers() {
+        let rating: Rating = serde_json::from_str("1500").unwrap();
+
+        assert_eq!(rating.rating(), 1500);
+        assert_eq!(rating.rd(), None);
+        assert!(!rating.is_provisional());
+    }
+
+    #[test]
+    fn provisional_ratings_are_displayed_with_a_question_mark() {
+        assert_eq!(
+            Rating::new(1500, Some(150), 0, 3, true).to_string(),
+            "1500?"
+        );
+        assert_eq!(
+            Rating::new(1500, Some(50), 0, 300, false).to_string(),
+            "1500"
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
+use strum_macros::EnumIter;
+
+/// Titles displayed next to a player's name, as used by lichess and chess.com
+// Variants are spelt the way titles are written, which is also how they are displayed and stored
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter, Serialize, Deserialize)]
+pub enum Title {
+    GM,
+    IM,
+    FM,
+    CM,
+    NM,
+    WGM,
+    WIM,
+    WFM,
+    WCM,
+    WNM,
+    LM,
+}
+
+impl FromStr for Title {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "GM" => Ok(Title::GM),
+            "IM" => Ok(Title::IM),
+            "FM" => Ok(Title::FM),
+            "CM" => Ok(Title::CM),
+            "NM" => Ok(Title::NM),
+            "WGM" => Ok(Title::WGM),
+            "WIM" => Ok(Title::WIM),
+            "WFM" => Ok(Title::WFM),
+            "WCM" => Ok(Title::WCM),
+            "WNM" => Ok(Title::WNM),
+            "LM" => Ok(Title::LM),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Display for Title {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use strum::IntoEnumIterator;
+
+    #[test]
+    fn title_can_be_parsed_back() {
+        for title in Title::iter() {
+            assert_eq!(title.to_string().parse::<Title>().unwrap(), title);
+        }
+    }
+
+    #[test]
+    fn title_parser_ignores_case_and_rejects_bots() {
+        assert_eq!("wgm".parse::<Title>().unwrap(), Title::WGM);
+        assert!("BOT".parse::<Title>().is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{bot, db, lichess, web};
+use crate::{bot, chesscom, db, lichess, web};
 
 pub async fn run() {
     trace!("run() called");
@@ -7,13 +7,14 @@ pub async fn run() {
 
     let pool = db::connect().await.expect("Couldn't connect to pool");
     let lichess = lichess::Client::new();
+    let chesscom = chesscom::Client::new();
 
     tokio::select! {
         _ = web::run(&pool, &lichess) => {
             info!("Web server exited.");
         }
 
-        _ = bot::run(&pool, &lichess) => {
+        _ = bot::run(&pool, &lichess, &chesscom) => {
             info!("Bot client exited.");
         }
     }
@@ -30,6 +30,36 @@ struct ErrorTemplate<'a> {
     message: &'a str,
 }
 
+/// Status and message shown when a request to lichess failed
+fn describe_lichess_error(e: &lichess::Error) -> (StatusCode, String) {
+    match e {
+        lichess::Error::NotFound => (
+            StatusCode::NOT_FOUND,
+            "Lichess couldn't find your account".to_string(),
+        ),
+        lichess::Error::Unauthorized => (
+            StatusCode::UNAUTHORIZED,
+            "Lichess didn't accept the authorization, please run the link command again"
+                .to_string(),
+        ),
+        lichess::Error::RateLimited(seconds) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            format!(
+                "Lichess is receiving too many requests from Liro, please try again in {} seconds",
+                seconds
+            ),
+        ),
+        lichess::Error::ServerError(_) | lichess::Error::Decode(_) => (
+            StatusCode::BAD_GATEWAY,
+            "Lichess seems to be having trouble right now, please try again later".to_string(),
+        ),
+        lichess::Error::Network(_) => (
+            StatusCode::BAD_GATEWAY,
+            "Liro couldn't reach lichess, please try again later".to_string(),
+        ),
+    }
+}
+
 pub async fn handle_rejection(err: Rejection) -> std::result::Result<impl Reply, Infallible> {
     trace!("handle_rejection() called");
     let code;
@@ -54,6 +84,10 @@ pub async fn handle_rejection(err: Rejection) -> std::result::Result<impl Reply,
                 code = StatusCode::CONFLICT;
                 message = e.to_string();
             }
+            Error::Lichess(e) => {
+                warn!("lichess error: {}", e);
+                (code, message) = describe_lichess_error(e);
+            }
             _ => {
                 error!("unhandled application error: {:?}", err);
                 code = StatusCode::INTERNAL_SERVER_ERROR;
@@ -3,6 +3,7 @@ use crate::{
     db::Pool,
     lichess,
     models::{Challenge, Guild, User},
+    ratings::Provider,
 };
 use askama::Template;
 use serde::Deserialize;
@@ -59,6 +60,11 @@ pub async fn oauth_callback_handler(
         .map_err(Error::Database)?
         .ok_or(Error::ChallengeNotFound)?;
 
+    // chess.com challenges are verified through the bot, never through OAuth
+    if challenge.chesscom_username().is_some() {
+        return Err(Error::ChallengeNotFound.into());
+    }
+
     let access_token = lichess
         .fetch_access_token(&params.code, &challenge.code_verifier())
         .await
@@ -75,7 +81,7 @@ pub async fn oauth_callback_handler(
 
     let username = lichess_user.get_username().to_string();
 
-    let user = User::find_by_username(&pool, challenge.guild_id(), &username)
+    let user = User::find_by_username(&pool, challenge.guild_id(), Provider::Lichess, &username)
         .await
         .map_err(Error::Database)?;
 
@@ -83,11 +89,12 @@ pub async fn oauth_callback_handler(
         return Err(Error::DuplicateLink.into());
     }
 
-    let user = User::new(
+    User::link(
         &pool,
         challenge.guild_id(),
         challenge.discord_id(),
-        username,
+        Provider::Lichess,
+        &username,
     )
     .await
     .map_err(Error::Database)?;
@@ -95,7 +102,7 @@ pub async fn oauth_callback_handler(
     challenge.delete(&pool).await.map_err(Error::Database)?;
 
     let template = AccountLinkedTemplate {
-        username: user.get_lichess_username(),
+        username: &username,
     };
 
     match template.render() {